@@ -50,6 +50,21 @@ pub struct Valid<'a> {
 }
 
 impl<'a> Valid<'a> {
+    /// Create a custom test case, e.g. for `toml_test_harness::DecoderHarness::extend_valid`
+    ///
+    /// `expected` is the decoded `fixture` in the toml-test tagged-JSON format.
+    pub fn new(
+        name: impl Into<Cow<'a, std::path::Path>>,
+        fixture: impl Into<Cow<'a, [u8]>>,
+        expected: impl Into<Cow<'a, [u8]>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            fixture: fixture.into(),
+            expected: expected.into(),
+        }
+    }
+
     pub fn borrow<'b: 'a>(&'b self) -> Valid<'b> {
         Self {
             name: Cow::Borrowed(self.name()),
@@ -128,6 +143,11 @@ impl<'a> Invalid<'a> {
     pub fn fixture(&self) -> &[u8] {
         self.fixture.as_ref()
     }
+
+    /// See [`error_location`]
+    pub fn error_location(&self) -> Option<(usize, usize)> {
+        error_location(self.name())
+    }
 }
 
 /// Returns all [`Invalid`] TOML test cases
@@ -145,6 +165,56 @@ pub fn invalid() -> impl Iterator<Item = Invalid<'static>> {
     })
 }
 
+const ERROR_LOCATIONS: &str = include_str!("../assets/error-locations.txt");
+
+/// Get the expected error location of an [`Invalid`] test case, as `(line, column)`
+///
+/// Lines and columns start at 1, with columns counting characters.  Not every case has a
+/// location.
+pub fn error_location(name: &std::path::Path) -> Option<(usize, usize)> {
+    static LOCATIONS: std::sync::OnceLock<
+        std::collections::HashMap<&'static std::path::Path, (usize, usize)>,
+    > = std::sync::OnceLock::new();
+    LOCATIONS
+        .get_or_init(|| {
+            ERROR_LOCATIONS
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    parse_error_location(line)
+                        .unwrap_or_else(|| panic!("invalid error location `{line}`"))
+                })
+                .collect()
+        })
+        .get(name)
+        .copied()
+}
+
+fn parse_error_location(line: &str) -> Option<(&std::path::Path, (usize, usize))> {
+    let (name, location) = line.rsplit_once(' ')?;
+    let (row, column) = location.split_once(':')?;
+    Some((
+        std::path::Path::new(name.trim_end()),
+        (row.parse().ok()?, column.parse().ok()?),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_location_entry() {
+        assert_eq!(
+            parse_error_location("invalid/table/duplicate.toml 3:2"),
+            Some((std::path::Path::new("invalid/table/duplicate.toml"), (3, 2)))
+        );
+        assert_eq!(parse_error_location("invalid/table/duplicate.toml"), None);
+        assert_eq!(parse_error_location("invalid/table/duplicate.toml 3"), None);
+    }
+}
+
 #[doc = include_str!("../README.md")]
 #[cfg(doctest)]
 pub struct ReadmeDoctests;
@@ -7,3 +7,35 @@ fn valid_doesnt_panic() {
 fn invalid_doesnt_panic() {
     toml_test_data::invalid().last().unwrap();
 }
+
+#[test]
+fn error_locations_are_invalid_cases() {
+    let entries = include_str!("../assets/error-locations.txt")
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .count();
+    let located = toml_test_data::invalid()
+        .filter(|case| case.error_location().is_some())
+        .count();
+    assert_eq!(entries, located);
+    assert_eq!(
+        toml_test_data::error_location(std::path::Path::new("invalid/does-not-exist.toml")),
+        None
+    );
+}
+
+#[test]
+fn error_locations_are_in_the_fixture() {
+    for case in toml_test_data::invalid() {
+        let Some((line, column)) = case.error_location() else {
+            continue;
+        };
+        let fixture = String::from_utf8_lossy(case.fixture());
+        let text = fixture.split('\n').nth(line - 1);
+        assert!(
+            text.is_some_and(|text| 1 <= column && column <= text.chars().count() + 1),
+            "{} has no line {line}, column {column}",
+            case.name().display()
+        );
+    }
+}
@@ -10,11 +10,51 @@
 #![warn(clippy::print_stderr)]
 #![warn(clippy::print_stdout)]
 
+pub use toml_test::ComparePolicy;
 pub use toml_test::DecodedScalar;
+pub use toml_test::DecodedTable;
 pub use toml_test::DecodedValue;
 pub use toml_test::Decoder;
+pub use toml_test::DecoderFn;
 pub use toml_test::Encoder;
+pub use toml_test::EncoderFn;
 pub use toml_test::Error;
+pub use toml_test::ErrorKind;
+pub use toml_test::ReferenceDecoder;
+pub use toml_test::ReferenceEncoder;
+pub use toml_test::SpecVersion;
+pub use toml_test::decoded;
+
+/// Create a custom [`Valid`][toml_test_data::Valid] case from a TOML `fixture` and how it decodes
+///
+/// See [`DecoderHarness::extend_valid`] and [`EncoderHarness::extend_valid`].
+///
+/// # Example
+///
+/// ```rust
+/// let case = toml_test_harness::valid_case(
+///     "custom/dates.toml",
+///     "date = 1979-05-27\ncount = 0x10\n",
+///     &toml_test_harness::decoded!({
+///         "date": date_local("1979-05-27"),
+///         "count": integer(16),
+///     }),
+/// );
+/// ```
+pub fn valid_case(
+    name: impl Into<std::path::PathBuf>,
+    fixture: impl Into<String>,
+    expected: &DecodedValue,
+) -> toml_test_data::Valid<'static> {
+    let expected = expected
+        .to_string_pretty()
+        .expect("`DecodedValue` always serializes");
+    toml_test_data::Valid::new(
+        name.into(),
+        fixture.into().into_bytes(),
+        expected.into_bytes(),
+    )
+}
 
 /// Run decoder compliance tests
 ///
@@ -51,23 +91,36 @@ pub struct DecoderHarness<D> {
     decoder: D,
     matches: Option<Matches>,
     version: Option<String>,
+    policy: ComparePolicy,
     custom_valid: Vec<toml_test_data::Valid<'static>>,
     custom_invalid: Vec<toml_test_data::Invalid<'static>>,
+    check_error_locations: bool,
+    error_location_matches: Option<Matches>,
+    #[cfg(feature = "preserve_order")]
+    check_key_order: bool,
     #[cfg(feature = "snapshot")]
     snapshot_root: Option<std::path::PathBuf>,
 }
 
 impl<D> DecoderHarness<D>
 where
-    D: Decoder + Copy + Send + Sync + 'static,
+    D: Decoder + Clone + Send + Sync + 'static,
 {
+    /// `decoder` is cloned for each case, so share expensive state through an `Arc`
+    ///
+    /// For closures, see [`DecoderFn`].
     pub fn new(decoder: D) -> Self {
         Self {
             decoder,
             matches: None,
             version: None,
+            policy: ComparePolicy::new(),
             custom_valid: Vec::new(),
             custom_invalid: Vec::new(),
+            check_error_locations: false,
+            error_location_matches: None,
+            #[cfg(feature = "preserve_order")]
+            check_key_order: false,
             #[cfg(feature = "snapshot")]
             snapshot_root: None,
         }
@@ -81,11 +134,21 @@ where
         Ok(self)
     }
 
+    /// Only run the cases for this version of the spec
+    ///
+    /// This also sets [`ComparePolicy::spec_version`], so payloads from a later version, like
+    /// TOML 1.1's secondless times, fail.
     pub fn version(&mut self, version: impl Into<String>) -> &mut Self {
         self.version = Some(version.into());
         self
     }
 
+    /// How strictly to compare decoded values, see [`ComparePolicy`]
+    pub fn compare_policy(&mut self, policy: ComparePolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
     pub fn extend_valid(
         &mut self,
         cases: impl IntoIterator<Item = toml_test_data::Valid<'static>>,
@@ -102,15 +165,43 @@ where
         self
     }
 
+    /// Also verify invalid cases fail at their [`toml_test_data::error_location`]
+    ///
+    /// Cases without a known location only need to fail.  See [`Error::with_span`] and
+    /// [`Error::with_line_column`] for reporting a location, which `toml_test::decoder_main`
+    /// passes on to a `toml_test::Command`.
+    pub fn check_error_locations(&mut self, yes: bool) -> &mut Self {
+        self.check_error_locations = yes;
+        self
+    }
+
+    /// Skip [`DecoderHarness::check_error_locations`] for cases matching `patterns`
+    pub fn ignore_error_locations<'p>(
+        &mut self,
+        patterns: impl IntoIterator<Item = &'p str>,
+    ) -> Result<&mut Self, Error> {
+        self.error_location_matches = Some(Matches::new(patterns.into_iter())?);
+        Ok(self)
+    }
+
+    /// Also verify tables are decoded with their keys in document order
+    #[cfg(feature = "preserve_order")]
+    pub fn check_key_order(&mut self, yes: bool) -> &mut Self {
+        self.check_key_order = yes;
+        self
+    }
+
     #[cfg(feature = "snapshot")]
     pub fn snapshot_root(&mut self, root: impl Into<std::path::PathBuf>) -> &mut Self {
         self.snapshot_root = Some(root.into());
         self
     }
 
+    /// Run the cases and exit
+    ///
+    /// Cases that could not be run, see [`ErrorKind::is_infrastructure`], are reported as ignored
+    /// and the run exits with [`INFRASTRUCTURE_EXIT_CODE`].
     pub fn test(self) -> ! {
-        let harness = libtest2_mimic::Harness::with_env();
-
         let versioned = self
             .version
             .as_deref()
@@ -120,6 +211,15 @@ where
 
         let mut tests = Vec::new();
         let decoder = self.decoder;
+        let spec_version = spec_version(self.version.as_deref());
+        let policy = versioned_policy(self.policy, spec_version);
+        let check_error_locations = self.check_error_locations;
+        let error_location_matches = self.error_location_matches;
+        #[cfg(feature = "preserve_order")]
+        let check_key_order = self.check_key_order;
+        // Custom cases and unknown versions get the most permissive document order
+        #[cfg(feature = "preserve_order")]
+        let key_order_version = spec_version.unwrap_or(SpecVersion::V1_1_0);
         #[cfg(feature = "snapshot")]
         let snapshot_root = self.snapshot_root;
         tests.extend(
@@ -137,14 +237,24 @@ where
                         .unwrap_or_default();
                     (case, ignore)
                 })
-                .map(move |(case, ignore)| {
-                    libtest2_mimic::Trial::test(case.name().display().to_string(), move |context| {
+                .map(|(case, ignore)| {
+                    let decoder = decoder.clone();
+                    Trial::test(case.name().display().to_string(), move |context| {
                         if ignore {
                             context.ignore()?;
                         }
-                        decoder
-                            .verify_valid_case(case.fixture(), case.expected())
-                            .map_err(libtest2_mimic::RunError::fail)
+                        verify(|| {
+                            decoder.verify_valid_case_with(
+                                case.fixture(),
+                                case.expected(),
+                                &policy,
+                            )?;
+                            #[cfg(feature = "preserve_order")]
+                            if check_key_order {
+                                decoder.verify_key_order(case.fixture(), key_order_version)?;
+                            }
+                            Ok(())
+                        })
                     })
                 }),
         );
@@ -164,31 +274,33 @@ where
                     (case, ignore)
                 })
                 .map(move |(case, ignore)| {
+                    let location = checked_error_location(
+                        &case,
+                        check_error_locations,
+                        error_location_matches.as_ref(),
+                    );
+                    let decoder = decoder.clone();
                     #[cfg(feature = "snapshot")]
                     let snapshot_root = snapshot_root.clone();
-                    libtest2_mimic::Trial::test(case.name().display().to_string(), move |context| {
+                    Trial::test(case.name().display().to_string(), move |context| {
                         if ignore {
                             context.ignore()?;
                         }
-                        match decoder.verify_invalid_case(case.fixture()) {
-                            Ok(_err) => {
-                                #[cfg(feature = "snapshot")]
-                                if let Some(snapshot_root) = snapshot_root.as_deref() {
-                                    let snapshot_path =
-                                        snapshot_root.join(case.name().with_extension("stderr"));
-                                    snapbox::assert_data_eq!(
-                                        _err.to_string(),
-                                        snapbox::Data::read_from(&snapshot_path, None).raw()
-                                    );
-                                }
-                                Ok(())
-                            }
-                            Err(err) => Err(libtest2_mimic::RunError::fail(err)),
+                        let _err = verify_invalid(&decoder, case.fixture(), location)?;
+                        #[cfg(feature = "snapshot")]
+                        if let Some(snapshot_root) = snapshot_root.as_deref() {
+                            let snapshot_path =
+                                snapshot_root.join(case.name().with_extension("stderr"));
+                            snapbox::assert_data_eq!(
+                                _err.to_string(),
+                                snapbox::Data::read_from(&snapshot_path, None).raw()
+                            );
                         }
+                        Ok(())
                     })
                 }),
         );
-        harness.discover(tests).main()
+        run(tests)
     }
 }
 
@@ -243,20 +355,37 @@ pub struct EncoderHarness<E, D> {
     fixture: D,
     matches: Option<Matches>,
     version: Option<String>,
+    policy: ComparePolicy,
     custom_valid: Vec<toml_test_data::Valid<'static>>,
 }
 
+impl<E> EncoderHarness<E, ReferenceDecoder>
+where
+    E: Encoder + Clone + Send + Sync + 'static,
+{
+    /// Verify `encoder` with the bundled [`ReferenceDecoder`], rather than a fixture of your own
+    pub fn with_reference_decoder(encoder: E, version: SpecVersion) -> Self {
+        let mut harness = Self::new(encoder, ReferenceDecoder::new(version));
+        harness.version(version.as_str());
+        harness
+    }
+}
+
 impl<E, D> EncoderHarness<E, D>
 where
-    E: Encoder + Copy + Send + Sync + 'static,
-    D: Decoder + Copy + Send + Sync + 'static,
+    E: Encoder + Clone + Send + Sync + 'static,
+    D: Decoder + Clone + Send + Sync + 'static,
 {
+    /// `encoder` and `fixture` are cloned for each case, so share expensive state through an `Arc`
+    ///
+    /// For closures, see [`EncoderFn`] and [`DecoderFn`].
     pub fn new(encoder: E, fixture: D) -> Self {
         Self {
             encoder,
             fixture,
             matches: None,
             version: None,
+            policy: ComparePolicy::new(),
             custom_valid: Vec::new(),
         }
     }
@@ -269,11 +398,21 @@ where
         Ok(self)
     }
 
+    /// Only run the cases for this version of the spec
+    ///
+    /// This also sets [`ComparePolicy::spec_version`], so payloads from a later version, like
+    /// TOML 1.1's secondless times, fail.
     pub fn version(&mut self, version: impl Into<String>) -> &mut Self {
         self.version = Some(version.into());
         self
     }
 
+    /// How strictly to compare decoded values, see [`ComparePolicy`]
+    pub fn compare_policy(&mut self, policy: ComparePolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
     pub fn extend_valid(
         &mut self,
         cases: impl IntoIterator<Item = toml_test_data::Valid<'static>>,
@@ -282,9 +421,11 @@ where
         self
     }
 
+    /// Run the cases and exit
+    ///
+    /// Cases that could not be run, see [`ErrorKind::is_infrastructure`], are reported as ignored
+    /// and the run exits with [`INFRASTRUCTURE_EXIT_CODE`].
     pub fn test(self) -> ! {
-        let harness = libtest2_mimic::Harness::with_env();
-
         let versioned = self
             .version
             .as_deref()
@@ -295,6 +436,7 @@ where
         let mut tests = Vec::new();
         let encoder = self.encoder;
         let fixture = self.fixture;
+        let policy = versioned_policy(self.policy, spec_version(self.version.as_deref()));
         tests.extend(
             toml_test_data::valid()
                 .map(|case| {
@@ -311,20 +453,223 @@ where
                     (case, ignore)
                 })
                 .map(move |(case, ignore)| {
-                    libtest2_mimic::Trial::test(case.name().display().to_string(), move |context| {
+                    let encoder = encoder.clone();
+                    let fixture = fixture.clone();
+                    Trial::test(case.name().display().to_string(), move |context| {
                         if ignore {
                             context.ignore()?;
                         }
-                        encoder
-                            .verify_valid_case(case.expected(), &fixture)
-                            .map_err(libtest2_mimic::RunError::fail)
+                        verify(|| {
+                            encoder.verify_valid_case_with(case.expected(), &fixture, &policy)
+                        })
                     })
                 }),
         );
-        harness.discover(tests).main()
+        run(tests)
+    }
+}
+
+/// The [`SpecVersion`] of a toml-test version, if supported
+fn spec_version(version: Option<&str>) -> Option<SpecVersion> {
+    version.and_then(|version| version.parse().ok())
+}
+
+fn versioned_policy(policy: ComparePolicy, version: Option<SpecVersion>) -> ComparePolicy {
+    match version {
+        Some(version) => policy.spec_version(version),
+        None => policy,
     }
 }
 
+/// Where an invalid case must fail, if its location is checked
+fn checked_error_location(
+    case: &toml_test_data::Invalid<'_>,
+    check: bool,
+    matches: Option<&Matches>,
+) -> Option<(usize, usize)> {
+    case.error_location().filter(|_| {
+        check
+            && matches
+                .map(|matches| matches.matched(case.name()))
+                .unwrap_or(true)
+    })
+}
+
+fn verify_invalid(
+    decoder: &impl Decoder,
+    fixture: &[u8],
+    location: Option<(usize, usize)>,
+) -> Result<Error, Failure> {
+    verify(|| match location {
+        Some((line, column)) => decoder.verify_invalid_case_at(fixture, line, column),
+        None => decoder.verify_invalid_case(fixture),
+    })
+}
+
+/// Run a case, labeling failures with their [`ErrorKind`]
+///
+/// Panics are reported as [`ErrorKind::Panic`] and infrastructure failures are kept apart so they
+/// aren't mistaken for conformance results.
+fn verify<T>(verify: impl FnOnce() -> Result<T, Error>) -> Result<T, Failure> {
+    let result =
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(verify)).unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(Error::new(message).with_kind(ErrorKind::Panic))
+        });
+    result.map_err(|err| {
+        if err.kind().is_infrastructure() {
+            Failure::Infrastructure(render_error(&err))
+        } else {
+            Failure::Conformance(render_error(&err))
+        }
+    })
+}
+
+fn render_error(err: &Error) -> String {
+    let kind = err.kind();
+    let mut rendered = if kind.is_infrastructure() {
+        format!("infrastructure failure ({kind}), not a conformance result: {err}")
+    } else if kind == ErrorKind::Other {
+        err.to_string()
+    } else {
+        format!("{kind}: {err}")
+    };
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        rendered.push_str(&format!("\ncaused by: {err}"));
+        source = err.source();
+    }
+    rendered
+}
+
+/// Exit status when a run had infrastructure failures
+///
+/// These cases are reported as ignored, rather than failed, as they say nothing about conformance.
+pub const INFRASTRUCTURE_EXIT_CODE: i32 = 2;
+
+/// Why a [`Trial`] did not pass
+enum Failure {
+    /// Skipped or otherwise decided by the test runner
+    Run(libtest2_harness::RunError),
+    /// The parser under test did not conform
+    Conformance(String),
+    /// The parser under test could not be run, see [`ErrorKind::is_infrastructure`]
+    Infrastructure(String),
+}
+
+impl From<libtest2_harness::RunError> for Failure {
+    fn from(err: libtest2_harness::RunError) -> Self {
+        Self::Run(err)
+    }
+}
+
+type Runner = Box<dyn Fn(&libtest2_harness::TestContext) -> Result<(), Failure> + Send + Sync>;
+
+struct Trial {
+    name: String,
+    runner: Runner,
+}
+
+impl Trial {
+    fn test(
+        name: String,
+        runner: impl Fn(&libtest2_harness::TestContext) -> Result<(), Failure> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            runner: Box::new(runner),
+        }
+    }
+}
+
+struct TrialCase {
+    trial: Trial,
+    infrastructure: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl libtest2_harness::Case for TrialCase {
+    fn name(&self) -> &str {
+        &self.trial.name
+    }
+    fn kind(&self) -> libtest2_harness::TestKind {
+        Default::default()
+    }
+    fn source(&self) -> Option<&libtest2_harness::Source> {
+        None
+    }
+    fn exclusive(&self, _: &libtest2_harness::TestContext) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        context: &libtest2_harness::TestContext,
+    ) -> Result<(), libtest2_harness::RunError> {
+        match (self.trial.runner)(context) {
+            Ok(()) => Ok(()),
+            Err(Failure::Run(err)) => Err(err),
+            Err(Failure::Conformance(message)) => Err(libtest2_harness::RunError::fail(message)),
+            Err(Failure::Infrastructure(message)) => {
+                self.infrastructure
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                context.ignore_for(&message)?;
+                Err(libtest2_harness::RunError::fail(message))
+            }
+        }
+    }
+}
+
+/// Run the trials and exit
+///
+/// Exits with [`INFRASTRUCTURE_EXIT_CODE`] if any case could not be run, whatever the other
+/// results.
+fn run(trials: Vec<Trial>) -> ! {
+    use std::io::Write as _;
+
+    let infrastructure = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let cases = trials.into_iter().map(|trial| TrialCase {
+        trial,
+        infrastructure: infrastructure.clone(),
+    });
+    let passed = match run_cases(cases) {
+        Ok(passed) => passed,
+        Err(err) => {
+            let _ = writeln!(std::io::stderr(), "{err}");
+            std::process::exit(libtest2_harness::ERROR_EXIT_CODE)
+        }
+    };
+    let infrastructure = infrastructure.load(std::sync::atomic::Ordering::Relaxed);
+    if infrastructure != 0 {
+        let _ = writeln!(
+            std::io::stderr(),
+            "{infrastructure} case(s) could not be run because of infrastructure failures; \
+             they are reported as ignored and are not conformance results"
+        );
+        std::process::exit(INFRASTRUCTURE_EXIT_CODE)
+    } else if passed {
+        std::process::exit(0)
+    } else {
+        std::process::exit(libtest2_harness::ERROR_EXIT_CODE)
+    }
+}
+
+fn run_cases(cases: impl Iterator<Item = TrialCase>) -> Result<bool, Error> {
+    let harness = libtest2_harness::Harness::new()
+        .with_env()
+        .map_err(|err| Error::io("failed to read test arguments", err))?;
+    let harness = harness.parse().map_err(|err| Error::new(err.to_string()))?;
+    let harness = harness
+        .discover(cases)
+        .map_err(|err| Error::io("failed to discover tests", err))?;
+    harness
+        .run()
+        .map_err(|err| Error::io("failed to run tests", err))
+}
+
 struct Matches {
     ignores: ignore::gitignore::Gitignore,
 }
@@ -347,6 +692,97 @@ impl Matches {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_sets_policy() {
+        let time = DecodedScalar::TimeLocal("07:32".to_owned());
+        let policy = versioned_policy(ComparePolicy::new(), spec_version(Some("1.0.0")));
+        assert!(time.compare_with(&time, &policy).is_err());
+        let policy = versioned_policy(ComparePolicy::new(), spec_version(Some("1.1.0")));
+        assert!(time.compare_with(&time, &policy).is_ok());
+        let policy = versioned_policy(ComparePolicy::new(), spec_version(Some("2.0.0")));
+        assert_eq!(policy, ComparePolicy::new());
+    }
+
+    #[test]
+    fn error_locations() {
+        let (case, (line, column)) = toml_test_data::invalid()
+            .find_map(|case| {
+                let location = case.error_location()?;
+                Some((case, location))
+            })
+            .expect("some invalid cases have a location");
+        let failing_at = |line: usize, column: usize| {
+            DecoderFn::new("failing", move |_: &[u8]| {
+                Err(Error::new("invalid").with_line_column(line, column))
+            })
+        };
+
+        let location = checked_error_location(&case, true, None);
+        assert_eq!(location, Some((line, column)));
+        assert!(verify_invalid(&failing_at(line, column), case.fixture(), location).is_ok());
+        assert!(verify_invalid(&failing_at(line, column + 1), case.fixture(), location).is_err());
+        assert!(verify_invalid(&failing_at(line + 1, column), case.fixture(), location).is_err());
+        let unlocated = DecoderFn::new("unlocated", |_: &[u8]| Err(Error::new("invalid")));
+        assert!(verify_invalid(&unlocated, case.fixture(), location).is_err());
+
+        assert_eq!(checked_error_location(&case, false, None), None);
+        let name = case.name().display().to_string();
+        let matches = Matches::new([name.as_str()].into_iter()).unwrap();
+        assert_eq!(checked_error_location(&case, true, Some(&matches)), None);
+        let matches = Matches::new(["invalid/does-not-exist.toml"].into_iter()).unwrap();
+        assert_eq!(
+            checked_error_location(&case, true, Some(&matches)),
+            Some((line, column))
+        );
+        assert!(verify_invalid(&unlocated, case.fixture(), None).is_ok());
+    }
+
+    #[test]
+    fn render() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(
+            render_error(&Error::io("failed to read stdin", io)),
+            "infrastructure failure (I/O), not a conformance result: failed to read stdin\ncaused by: missing"
+        );
+        assert_eq!(
+            render_error(&Error::new("hung").with_kind(ErrorKind::Timeout)),
+            "timeout: hung"
+        );
+        assert_eq!(render_error(&Error::new("rejected")), "rejected");
+    }
+
+    #[test]
+    fn infrastructure() {
+        let failure = |kind| verify(|| Err::<(), _>(Error::new("broken").with_kind(kind)));
+        for kind in [ErrorKind::Io, ErrorKind::Spawn, ErrorKind::Protocol] {
+            assert!(
+                matches!(failure(kind), Err(Failure::Infrastructure(_))),
+                "{kind}"
+            );
+        }
+        for kind in [
+            ErrorKind::Other,
+            ErrorKind::Mismatch,
+            ErrorKind::UnexpectedSuccess,
+            ErrorKind::Timeout,
+            ErrorKind::Panic,
+        ] {
+            assert!(
+                matches!(failure(kind), Err(Failure::Conformance(_))),
+                "{kind}"
+            );
+        }
+        assert!(matches!(
+            verify(|| -> Result<(), Error> { panic!("boom") }),
+            Err(Failure::Conformance(message)) if message == "panic: boom"
+        ));
+    }
+}
+
 #[doc = include_str!("../README.md")]
 #[cfg(doctest)]
 pub struct ReadmeDoctests;
@@ -0,0 +1,20 @@
+//! Check the reference decoder against `toml-test-data/assets/error-locations.txt`
+
+use toml_test::Decoder as _;
+
+fn main() {
+    let decoder = toml_test::ReferenceDecoder::new(toml_test::SpecVersion::V1_1_0);
+    let mut checked = 0;
+    let mut differences = 0;
+    for case in toml_test_data::invalid() {
+        let Some((line, column)) = case.error_location() else {
+            continue;
+        };
+        checked += 1;
+        if let Err(err) = decoder.verify_invalid_case_at(case.fixture(), line, column) {
+            differences += 1;
+            println!("{}: {err}", case.name().display());
+        }
+    }
+    println!("{differences} of {checked} locations differ");
+}
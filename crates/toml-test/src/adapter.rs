@@ -0,0 +1,163 @@
+use crate::DecodedValue;
+use crate::Decoder;
+use crate::Encoder;
+
+/// A [`Decoder`] from a function or closure
+///
+/// ```rust
+/// use toml_test::Decoder as _;
+///
+/// let version = toml_test::SpecVersion::V1_1_0;
+/// let decoder = toml_test::DecoderFn::new("reference", move |data: &[u8]| {
+///     toml_test::ReferenceDecoder::new(version).decode(data)
+/// });
+/// assert!(decoder.decode(b"a = 1").is_ok());
+/// ```
+///
+/// For trait objects, [`Decoder`] is implemented for `&D`, `Box<D>` and `Arc<D>`.
+#[derive(Copy, Clone)]
+pub struct DecoderFn<F> {
+    name: &'static str,
+    decode: F,
+}
+
+impl<F> DecoderFn<F>
+where
+    F: Fn(&[u8]) -> Result<DecodedValue, crate::Error>,
+{
+    pub const fn new(name: &'static str, decode: F) -> Self {
+        Self { name, decode }
+    }
+}
+
+impl<F> Decoder for DecoderFn<F>
+where
+    F: Fn(&[u8]) -> Result<DecodedValue, crate::Error>,
+{
+    fn decode(&self, data: &[u8]) -> Result<DecodedValue, crate::Error> {
+        (self.decode)(data)
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl<F> std::fmt::Debug for DecoderFn<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecoderFn")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`Encoder`] from a function or closure
+///
+/// ```rust
+/// use toml_test::Encoder as _;
+///
+/// let encoder = toml_test::EncoderFn::new("reference", |data| {
+///     toml_test::ReferenceEncoder::default().to_toml(&data)
+/// });
+/// assert_eq!(encoder.encode(toml_test::decoded!({})).unwrap(), "");
+/// ```
+///
+/// For trait objects, [`Encoder`] is implemented for `&E`, `Box<E>` and `Arc<E>`.
+#[derive(Copy, Clone)]
+pub struct EncoderFn<F> {
+    name: &'static str,
+    encode: F,
+}
+
+impl<F> EncoderFn<F>
+where
+    F: Fn(DecodedValue) -> Result<String, crate::Error>,
+{
+    pub const fn new(name: &'static str, encode: F) -> Self {
+        Self { name, encode }
+    }
+}
+
+impl<F> Encoder for EncoderFn<F>
+where
+    F: Fn(DecodedValue) -> Result<String, crate::Error>,
+{
+    fn encode(&self, data: DecodedValue) -> Result<String, crate::Error> {
+        (self.encode)(data)
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+impl<F> std::fmt::Debug for EncoderFn<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncoderFn")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+macro_rules! impl_pointers {
+    ($($ptr:ty),*) => {
+        $(
+            impl<D: Decoder + ?Sized> Decoder for $ptr {
+                fn decode(&self, data: &[u8]) -> Result<DecodedValue, crate::Error> {
+                    (**self).decode(data)
+                }
+
+                fn name(&self) -> &str {
+                    (**self).name()
+                }
+            }
+
+            impl<D: Encoder + ?Sized> Encoder for $ptr {
+                fn encode(&self, data: DecodedValue) -> Result<String, crate::Error> {
+                    (**self).encode(data)
+                }
+
+                fn name(&self) -> &str {
+                    (**self).name()
+                }
+            }
+        )*
+    };
+}
+
+impl_pointers!(&D, Box<D>, std::sync::Arc<D>);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pointers() {
+        let reference = crate::ReferenceDecoder::default();
+        let decoders: [std::sync::Arc<dyn Decoder + Send + Sync>; 3] = [
+            std::sync::Arc::new(reference),
+            std::sync::Arc::new(Box::new(reference) as Box<dyn Decoder + Send + Sync>),
+            std::sync::Arc::new(DecoderFn::new("fn", move |data: &[u8]| {
+                reference.decode(data)
+            })),
+        ];
+        for decoder in &decoders {
+            assert_eq!(
+                decoder.decode(b"a = 1").unwrap(),
+                crate::decoded!({ "a": integer(1) })
+            );
+        }
+        assert_eq!(decoders[0].name(), "toml-test");
+        assert_eq!(decoders[1].name(), "toml-test");
+        assert_eq!(decoders[2].name(), "fn");
+
+        let encoder: Box<dyn Encoder> = Box::new(EncoderFn::new("fn", |data| {
+            crate::ReferenceEncoder::default().to_toml(&data)
+        }));
+        let fixture: &dyn Decoder = &decoders[0];
+        encoder
+            .verify_valid_case(br#"{"a": {"type": "integer", "value": "1"}}"#, fixture)
+            .unwrap();
+        assert_eq!(encoder.name(), "fn");
+    }
+}
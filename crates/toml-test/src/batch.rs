@@ -0,0 +1,386 @@
+//! The batch protocol, see [`BATCH_ENV`][crate::BATCH_ENV]
+
+use std::io::BufRead;
+use std::io::Read as _;
+use std::io::Write;
+use std::process::Child;
+use std::process::ChildStdin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// First line written by a batch server
+pub(crate) const HANDSHAKE: &str = "toml-test batch 1";
+
+/// How long a batch server has to complete the handshake, when there is no timeout
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    Decode,
+    Encode,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Decode => "decode",
+            Op::Encode => "encode",
+        }
+    }
+}
+
+/// Answer requests from `input` until it is closed
+pub(crate) fn serve(
+    run: impl Fn(Op, &[u8]) -> Result<String, crate::Error>,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), crate::Error> {
+    let write_failed = |err| crate::Error::io("failed to write a batch response", err);
+    writeln!(output, "{HANDSHAKE}").map_err(write_failed)?;
+    output.flush().map_err(write_failed)?;
+    while let Some(header) = read_header(&mut input)
+        .map_err(|err| crate::Error::io("failed to read a batch request", err))?
+    {
+        let op = match header.word.as_str() {
+            "decode" => Op::Decode,
+            "encode" => Op::Encode,
+            op => return Err(crate::Error::new(format!("unknown batch request `{op}`"))),
+        };
+        let mut data = vec![0; header.len];
+        input
+            .read_exact(&mut data)
+            .map_err(|err| crate::Error::io("failed to read a batch request", err))?;
+        let (status, body, location) = match run(op, &data) {
+            Ok(body) => ("ok", body, None),
+            Err(err) => ("err", err.to_string(), err.line_column_in(&data)),
+        };
+        write!(output, "{status} {}", body.len()).map_err(write_failed)?;
+        if let Some((line, column)) = location {
+            write!(output, " {line}:{column}").map_err(write_failed)?;
+        }
+        writeln!(output).map_err(write_failed)?;
+        output.write_all(body.as_bytes()).map_err(write_failed)?;
+        output.flush().map_err(write_failed)?;
+    }
+    Ok(())
+}
+
+struct Header {
+    word: String,
+    len: usize,
+    location: Option<(usize, usize)>,
+}
+
+/// Read a `<word> <len>` or `<word> <len> <line>:<column>` line, returning `None` at the end of
+/// `input`
+fn read_header(input: &mut impl BufRead) -> std::io::Result<Option<Header>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid batch header {line:?}"),
+        )
+    };
+    let (word, len) = line
+        .strip_suffix('\n')
+        .and_then(|line| line.split_once(' '))
+        .ok_or_else(invalid)?;
+    let (len, location) = match len.split_once(' ') {
+        Some((len, location)) => (
+            len,
+            Some(crate::bin::parse_location(location).ok_or_else(invalid)?),
+        ),
+        None => (len, None),
+    };
+    let len = len.parse::<usize>().map_err(|_| invalid())?;
+    Ok(Some(Header {
+        word: word.to_owned(),
+        len,
+        location,
+    }))
+}
+
+pub(crate) enum BatchError {
+    /// The binary doesn't speak the batch protocol
+    Unsupported,
+    /// The binary reported an error for this request, with its location
+    Failed(String, Option<(usize, usize)>),
+    /// The binary exited or broke protocol, with its stderr
+    Crashed(String),
+    TimedOut,
+    OutputExceeded,
+}
+
+/// A batch server, started on first use
+#[derive(Default)]
+pub(crate) struct Session {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Idle,
+    Running(Server),
+    Unsupported,
+}
+
+struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    events: mpsc::Receiver<Event>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+    /// Disconnects once all of stderr is read
+    stderr_done: mpsc::Receiver<()>,
+}
+
+enum Event {
+    Ready,
+    Response(Vec<u8>),
+    /// An error response, with its location
+    Failed(Vec<u8>, Option<(usize, usize)>),
+    OutputExceeded,
+    Closed,
+}
+
+impl Session {
+    pub(crate) fn request(
+        &self,
+        spawn: &dyn Fn() -> std::io::Result<Child>,
+        op: Op,
+        input: &[u8],
+        timeout: Option<Duration>,
+        max_output: Option<usize>,
+    ) -> Result<Vec<u8>, BatchError> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if matches!(*state, State::Idle) {
+            *state = match Server::start(spawn, timeout, max_output) {
+                Some(server) => State::Running(server),
+                None => State::Unsupported,
+            };
+        }
+        let State::Running(server) = &mut *state else {
+            return Err(BatchError::Unsupported);
+        };
+
+        let result = server.request(op, input, timeout);
+        if matches!(
+            result,
+            Err(BatchError::Crashed(_) | BatchError::TimedOut | BatchError::OutputExceeded)
+        ) {
+            // Restart on the next request
+            *state = State::Idle;
+        }
+        result
+    }
+}
+
+impl Server {
+    fn start(
+        spawn: &dyn Fn() -> std::io::Result<Child>,
+        timeout: Option<Duration>,
+        max_output: Option<usize>,
+    ) -> Option<Self> {
+        let mut child = spawn().ok()?;
+        let stdin = child.stdin.take()?;
+        let stdout = std::io::BufReader::new(child.stdout.take()?);
+        let mut stderr_pipe = child.stderr.take()?;
+
+        let max_output = max_output.map(|bytes| bytes as u64).unwrap_or(u64::MAX);
+        let (sender, events) = mpsc::channel();
+        let stderr_events = sender.clone();
+        std::thread::spawn(move || read_events(stdout, max_output, &sender));
+        let stderr = Arc::new(Mutex::new(Vec::new()));
+        let buffer = stderr.clone();
+        let (stderr_sender, stderr_done) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let _stderr_sender = stderr_sender;
+            let mut chunk = [0; 4096];
+            while let Ok(len @ 1..) = stderr_pipe.read(&mut chunk) {
+                let mut buffer = buffer.lock().unwrap_or_else(|err| err.into_inner());
+                // The buffer is cleared per request, so this is the limit for one case
+                if max_output < (buffer.len() + len) as u64 {
+                    let _ = stderr_events.send(Event::OutputExceeded);
+                    return;
+                }
+                buffer.extend_from_slice(&chunk[..len]);
+            }
+        });
+
+        let mut server = Self {
+            child,
+            stdin,
+            events,
+            stderr,
+            stderr_done,
+        };
+        match server
+            .events
+            .recv_timeout(timeout.unwrap_or(HANDSHAKE_TIMEOUT))
+        {
+            Ok(Event::Ready) => Some(server),
+            _ => {
+                server.kill();
+                None
+            }
+        }
+    }
+
+    fn request(
+        &mut self,
+        op: Op,
+        input: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, BatchError> {
+        self.stderr
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+        let written = writeln!(self.stdin, "{} {}", op.as_str(), input.len())
+            .and_then(|()| self.stdin.write_all(input))
+            .and_then(|()| self.stdin.flush());
+        let event = match (written, timeout) {
+            (Err(_), _) => Ok(Event::Closed),
+            (Ok(()), Some(timeout)) => self.events.recv_timeout(timeout),
+            (Ok(()), None) => self
+                .events
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(Event::Response(output)) => Ok(output),
+            Ok(Event::Failed(message, location)) => Err(BatchError::Failed(
+                String::from_utf8_lossy(&message).into_owned(),
+                location,
+            )),
+            Ok(Event::OutputExceeded) => {
+                self.kill();
+                Err(BatchError::OutputExceeded)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.kill();
+                Err(BatchError::TimedOut)
+            }
+            Ok(Event::Ready | Event::Closed) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                self.kill();
+                // Grandchildren may keep stderr open, so only wait briefly
+                let _ = self.stderr_done.recv_timeout(Duration::from_millis(100));
+                let stderr = self.stderr.lock().unwrap_or_else(|err| err.into_inner());
+                Err(BatchError::Crashed(
+                    String::from_utf8_lossy(&stderr).into_owned(),
+                ))
+            }
+        }
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+fn read_events(mut stdout: impl BufRead, max_output: u64, sender: &mpsc::Sender<Event>) {
+    let mut line = String::new();
+    let ready = stdout.read_line(&mut line).is_ok() && line.trim_end() == HANDSHAKE;
+    if !ready || sender.send(Event::Ready).is_err() {
+        let _ = sender.send(Event::Closed);
+        return;
+    }
+    loop {
+        let event = match read_header(&mut stdout) {
+            Ok(Some(header)) if max_output < header.len as u64 => Event::OutputExceeded,
+            Ok(Some(header)) => {
+                let mut body = vec![0; header.len];
+                match (stdout.read_exact(&mut body), header.word.as_str()) {
+                    (Ok(()), "ok") => Event::Response(body),
+                    (Ok(()), "err") => Event::Failed(body, header.location),
+                    _ => Event::Closed,
+                }
+            }
+            Ok(None) | Err(_) => Event::Closed,
+        };
+        let done = !matches!(event, Event::Response(_) | Event::Failed(..));
+        if sender.send(event).is_err() || done {
+            return;
+        }
+    }
+}
+
+impl PartialEq for Session {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Session {}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn protocol() {
+        let run = |op: Op, input: &[u8]| match (op, input) {
+            (Op::Decode, b"a") => Ok("A".to_owned()),
+            (Op::Decode, b"\nb") => Err(crate::Error::new("bad").with_span(1..2)),
+            _ => Err(crate::Error::new("oops")),
+        };
+        let mut output = Vec::new();
+        let input = &b"decode 1\nadecode 0\nencode 1\nadecode 2\n\nb"[..];
+        serve(run, input, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "toml-test batch 1\nok 1\nAerr 4\noopserr 4\noopserr 3 2:1\nbad"
+        );
+
+        let err = serve(run, &b"decode 1"[..], std::io::sink()).unwrap_err();
+        assert_eq!(err.to_string(), "failed to read a batch request");
+        assert_eq!(
+            std::error::Error::source(&err).unwrap().to_string(),
+            r#"invalid batch header "decode 1""#
+        );
+        let err = serve(run, &b"parse 0\n"[..], std::io::sink()).unwrap_err();
+        assert_eq!(err.to_string(), "unknown batch request `parse`");
+    }
+
+    #[test]
+    fn events() {
+        let (sender, events) = mpsc::channel();
+        let stdout = &b"toml-test batch 1\nok 2\n{}err 4\noopserr 3 2:5\nbadok 5\n12345ok 1\n"[..];
+        read_events(stdout, 4, &sender);
+        drop(sender);
+        assert!(matches!(events.recv(), Ok(Event::Ready)));
+        assert!(matches!(events.recv(), Ok(Event::Response(body)) if body == b"{}"));
+        assert!(matches!(
+            events.recv(),
+            Ok(Event::Failed(body, None)) if body == b"oops"
+        ));
+        assert!(matches!(
+            events.recv(),
+            Ok(Event::Failed(body, Some((2, 5)))) if body == b"bad"
+        ));
+        assert!(matches!(events.recv(), Ok(Event::OutputExceeded)));
+        assert!(events.recv().is_err());
+
+        let (sender, events) = mpsc::channel();
+        read_events(&b"{}\n"[..], u64::MAX, &sender);
+        assert!(matches!(events.recv(), Ok(Event::Closed)));
+    }
+}
@@ -0,0 +1,278 @@
+//! The `toml-test` binary protocol
+//!
+//! - Decoders read TOML from stdin and write tagged JSON to stdout
+//! - Encoders read tagged JSON from stdin and write TOML to stdout
+//! - On failure, the error is written to stderr and the binary exits with `1`, see
+//!   [`decoder_main`] for the error location
+//!
+//! See [`BATCH_ENV`] for handling many cases in one process.
+
+use std::ffi::OsString;
+use std::io::Read as _;
+use std::io::Write as _;
+use std::process::ExitCode;
+
+use crate::DecodedValue;
+use crate::Decoder;
+use crate::Encoder;
+use crate::SpecVersion;
+use crate::batch::Op;
+
+/// Environment variable selecting the [`SpecVersion`] of [`decoder_main`] and [`encoder_main`]
+///
+/// This is set by [`Command::spec_version`][crate::Command::spec_version].
+pub const SPEC_VERSION_ENV: &str = "TOML_TEST_VERSION";
+
+/// Environment variable requesting the batch protocol from [`decoder_main`] and [`encoder_main`]
+///
+/// When set to `1`, one process handles many cases:
+/// 1. The binary writes `toml-test batch 1\n` once it is ready
+/// 2. Each request is a `decode <len>\n` or `encode <len>\n` line followed by `<len>` bytes of
+///    input
+/// 3. Each response is an `ok <len>\n` or `err <len>\n` line followed by `<len>` bytes of output
+///    or of the error message.  Errors with a location use `err <len> <line>:<column>\n`.
+/// 4. The binary exits once stdin is closed
+///
+/// This is set by [`Command::batch`][crate::Command::batch].
+pub const BATCH_ENV: &str = "TOML_TEST_BATCH";
+
+/// Implement `main` for a decoder binary, see [`Command`][crate::Command]
+///
+/// `decoder` is called with the [`SpecVersion`] selected by `--toml <version>` or else
+/// [`SPEC_VERSION_ENV`], or `None` when neither is set, leaving the default to the
+/// implementation.
+///
+/// When an error has a [location][crate::Error::line_column_in], the last line written to stderr
+/// is `toml-test location: <line>:<column>`, for [`Decoder::verify_invalid_case_at`].
+///
+/// ```rust,no_run
+/// fn main() -> std::process::ExitCode {
+///     toml_test::decoder_main(|version| {
+///         toml_test::ReferenceDecoder::new(version.unwrap_or_default())
+///     })
+/// }
+/// ```
+pub fn decoder_main<D: Decoder>(decoder: impl FnOnce(Option<SpecVersion>) -> D) -> ExitCode {
+    main(Op::Decode, |version| {
+        let decoder = decoder(version);
+        move |op: Op, input: &[u8]| match op {
+            Op::Decode => run_decoder(&decoder, input),
+            Op::Encode => Err(crate::Error::new("encoding is not supported")),
+        }
+    })
+}
+
+/// Implement `main` for an encoder binary, see [`Command`][crate::Command]
+///
+/// `encoder` is called with the [`SpecVersion`] selected by `--toml <version>` or else
+/// [`SPEC_VERSION_ENV`], or `None` when neither is set, leaving the default to the
+/// implementation.
+///
+/// ```rust,no_run
+/// fn main() -> std::process::ExitCode {
+///     toml_test::encoder_main(|version| {
+///         toml_test::ReferenceEncoder::new(version.unwrap_or_default())
+///     })
+/// }
+/// ```
+pub fn encoder_main<E: Encoder>(encoder: impl FnOnce(Option<SpecVersion>) -> E) -> ExitCode {
+    main(Op::Encode, |version| {
+        let encoder = encoder(version);
+        move |op: Op, input: &[u8]| match op {
+            Op::Decode => Err(crate::Error::new("decoding is not supported")),
+            Op::Encode => run_encoder(&encoder, input),
+        }
+    })
+}
+
+fn main<R>(op: Op, setup: impl FnOnce(Option<SpecVersion>) -> R) -> ExitCode
+where
+    R: Fn(Op, &[u8]) -> Result<String, crate::Error>,
+{
+    let result = spec_version(
+        std::env::args_os().skip(1),
+        std::env::var_os(SPEC_VERSION_ENV),
+    )
+    .and_then(|version| {
+        let run = setup(version);
+        if std::env::var_os(BATCH_ENV).is_some_and(|batch| batch == "1") {
+            return crate::batch::serve(&run, std::io::stdin().lock(), std::io::stdout().lock());
+        }
+
+        let mut input = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|err| crate::Error::io("failed to read stdin", err))?;
+        let output = run(op, &input).map_err(|err| match err.line_column_in(&input) {
+            Some((line, column)) => err.with_line_column(line, column),
+            None => err,
+        })?;
+        std::io::stdout()
+            .write_all(output.as_bytes())
+            .map_err(|err| crate::Error::io("failed to write stdout", err))
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let mut stderr = std::io::stderr().lock();
+            let _ = writeln!(stderr, "{err}");
+            let mut source = std::error::Error::source(&err);
+            while let Some(err) = source {
+                let _ = writeln!(stderr, "caused by: {err}");
+                source = err.source();
+            }
+            if let (Some(line), Some(column)) = (err.line(), err.column()) {
+                let _ = writeln!(stderr, "{LOCATION_PREFIX}{line}:{column}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Starts the stderr line with the location of an error
+pub(crate) const LOCATION_PREFIX: &str = "toml-test location: ";
+
+/// Parse `<line>:<column>`
+pub(crate) fn parse_location(location: &str) -> Option<(usize, usize)> {
+    let (line, column) = location.split_once(':')?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+/// Split the location off the end of a binary's stderr, see [`decoder_main`]
+pub(crate) fn split_location(stderr: &str) -> (&str, Option<(usize, usize)>) {
+    let trimmed = stderr.strip_suffix('\n').unwrap_or(stderr);
+    let (message, last) = match trimmed.rsplit_once('\n') {
+        Some((message, last)) => (&stderr[..message.len() + 1], last),
+        None => ("", trimmed),
+    };
+    match last.strip_prefix(LOCATION_PREFIX).and_then(parse_location) {
+        Some(location) => (message, Some(location)),
+        None => (stderr, None),
+    }
+}
+
+fn spec_version(
+    mut args: impl Iterator<Item = OsString>,
+    env: Option<OsString>,
+) -> Result<Option<SpecVersion>, crate::Error> {
+    let mut version = None;
+    while let Some(arg) = args.next() {
+        let arg = arg
+            .into_string()
+            .map_err(|arg| crate::Error::new(format!("unexpected argument {arg:?}")))?;
+        let value = if arg == "--toml" {
+            args.next()
+                .ok_or_else(|| crate::Error::new("`--toml` requires a version"))?
+                .into_string()
+                .map_err(|value| crate::Error::new(format!("invalid TOML version {value:?}")))?
+        } else if let Some(value) = arg.strip_prefix("--toml=") {
+            value.to_owned()
+        } else {
+            return Err(crate::Error::new(format!("unexpected argument `{arg}`")));
+        };
+        version = Some(value.parse::<SpecVersion>()?);
+    }
+    if version.is_none() {
+        if let Some(env) = env.filter(|env| !env.is_empty()) {
+            let env = env
+                .into_string()
+                .map_err(|env| crate::Error::new(format!("invalid {SPEC_VERSION_ENV} {env:?}")))?;
+            version = Some(env.parse::<SpecVersion>()?);
+        }
+    }
+    Ok(version)
+}
+
+fn run_decoder(decoder: &dyn Decoder, input: &[u8]) -> Result<String, crate::Error> {
+    let mut output = decoder.decode(input)?.to_string_pretty()?;
+    output.push('\n');
+    Ok(output)
+}
+
+fn run_encoder(encoder: &dyn Encoder, input: &[u8]) -> Result<String, crate::Error> {
+    encoder.encode(DecodedValue::from_slice(input)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = OsString> {
+        args.iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn select_spec_version() {
+        assert_eq!(spec_version(args(&[]), None).unwrap(), None);
+        assert_eq!(
+            spec_version(args(&["--toml", "1.1"]), None).unwrap(),
+            Some(SpecVersion::V1_1_0)
+        );
+        assert_eq!(
+            spec_version(args(&["--toml=1.0.0"]), Some("1.1".into())).unwrap(),
+            Some(SpecVersion::V1_0_0)
+        );
+        assert_eq!(
+            spec_version(args(&[]), Some("1.1.0".into())).unwrap(),
+            Some(SpecVersion::V1_1_0)
+        );
+        assert_eq!(spec_version(args(&[]), Some("".into())).unwrap(), None);
+
+        assert_eq!(
+            spec_version(args(&["--toml"]), None)
+                .unwrap_err()
+                .to_string(),
+            "`--toml` requires a version"
+        );
+        assert_eq!(
+            spec_version(args(&["--decode"]), None)
+                .unwrap_err()
+                .to_string(),
+            "unexpected argument `--decode`"
+        );
+        assert_eq!(
+            spec_version(args(&[]), Some("2".into()))
+                .unwrap_err()
+                .to_string(),
+            "unsupported TOML version `2`, expected one of 1.0.0, 1.1.0"
+        );
+    }
+
+    #[test]
+    fn run() {
+        let decoder = crate::ReferenceDecoder::new(SpecVersion::V1_0_0);
+        let encoder = crate::ReferenceEncoder::new(SpecVersion::V1_0_0);
+
+        let json = run_decoder(&decoder, b"a = 1").unwrap();
+        assert_eq!(
+            DecodedValue::from_slice(json.as_bytes()).unwrap(),
+            crate::decoded!({ "a": integer(1) })
+        );
+        let toml = run_encoder(&encoder, json.as_bytes()).unwrap();
+        assert_eq!(toml, "a = 1\n");
+
+        assert!(run_decoder(&decoder, b"a = ").is_err());
+        assert!(run_encoder(&encoder, b"a = 1").is_err());
+    }
+
+    #[test]
+    fn location() {
+        assert_eq!(
+            split_location("oops\ntoml-test location: 2:5\n"),
+            ("oops\n", Some((2, 5)))
+        );
+        assert_eq!(
+            split_location("toml-test location: 2:5"),
+            ("", Some((2, 5)))
+        );
+        assert_eq!(
+            split_location("oops\ntoml-test location: 2\n"),
+            ("oops\ntoml-test location: 2\n", None)
+        );
+        assert_eq!(split_location("oops\n"), ("oops\n", None));
+        assert_eq!(split_location(""), ("", None));
+    }
+}
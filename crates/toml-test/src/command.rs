@@ -0,0 +1,774 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::io::Read as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Decoder;
+use crate::Encoder;
+use crate::ErrorKind;
+use crate::batch::BatchError;
+use crate::batch::Op;
+
+/// TOML parser-as-a-binary
+///
+/// The binary is run once per case, reading the input from stdin and writing the output to
+/// stdout, see [`decoder_main`][crate::decoder_main] and [`encoder_main`][crate::encoder_main].
+/// For binaries with a slow startup, see [`Command::batch`].
+///
+/// ```rust
+/// let decoder = toml_test::Command::new("python3")
+///     .args(["-m", "tomllib_shim", "--decode"])
+///     .env("PYTHONUTF8", "1")
+///     .spec_version(toml_test::SpecVersion::V1_1_0);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    bin: PathBuf,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    stdin_encoding: StdinEncoding,
+    format: crate::JsonFormat,
+    spec_version: Option<crate::SpecVersion>,
+    timeout: Option<Duration>,
+    max_output: Option<usize>,
+    memory_limit: Option<u64>,
+    batch: bool,
+    session: Arc<crate::batch::Session>,
+}
+
+impl Command {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            bin: path.as_ref().to_owned(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            current_dir: None,
+            stdin_encoding: StdinEncoding::Raw,
+            format: crate::JsonFormat::Current,
+            spec_version: None,
+            timeout: None,
+            max_output: None,
+            memory_limit: None,
+            batch: false,
+            session: Default::default(),
+        }
+    }
+
+    /// Parse a shell-style command line, e.g. `python3 -m tomllib_shim --decode`
+    ///
+    /// Words are split on whitespace, with `'...'`, `"..."` and `\` quoting like a POSIX shell.
+    /// Leading `NAME=value` words set environment variables.  There is no expansion of
+    /// variables, globs, etc.
+    pub fn from_command_line(line: &str) -> Result<Self, crate::Error> {
+        let mut words = split_command_line(line)?.into_iter();
+        let mut envs = Vec::new();
+        let bin = loop {
+            let word = words
+                .next()
+                .ok_or_else(|| crate::Error::new(format!("no program in {line:?}")))?;
+            match word.split_once('=') {
+                Some((key, value)) if is_env_name(key) => {
+                    envs.push((key.to_owned(), value.to_owned()));
+                }
+                _ => break word,
+            }
+        };
+        let mut command = Self::new(bin).args(words);
+        for (key, value) in envs {
+            command = command.env(key, value);
+        }
+        Ok(command)
+    }
+
+    /// Read a command line from the environment variable `key`, see
+    /// [`Command::from_command_line`]
+    ///
+    /// Returns `None` when the variable is unset or empty.
+    ///
+    /// ```rust,no_run
+    /// let decoder = toml_test::Command::from_env("TOML_DECODER")
+    ///     .unwrap()
+    ///     .expect("TOML_DECODER is unset");
+    /// ```
+    pub fn from_env(key: &str) -> Result<Option<Self>, crate::Error> {
+        match std::env::var(key) {
+            Ok(line) if line.trim().is_empty() => Ok(None),
+            Ok(line) => Self::from_command_line(&line).map(Some),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(err) => Err(crate::Error::new(format!("invalid {key}: {err}"))),
+        }
+    }
+
+    /// Append an argument to the binary
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self.session = Default::default();
+        self
+    }
+
+    /// Append arguments to the binary
+    pub fn args(mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Self {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self.session = Default::default();
+        self
+    }
+
+    /// Set an environment variable for the binary, inheriting the rest
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self.session = Default::default();
+        self
+    }
+
+    /// The working directory of the binary, defaulting to the current one
+    pub fn current_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.current_dir = Some(dir.as_ref().to_owned());
+        self.session = Default::default();
+        self
+    }
+
+    /// How the input is written to the binary's stdin
+    pub fn stdin_encoding(mut self, encoding: StdinEncoding) -> Self {
+        self.stdin_encoding = encoding;
+        self
+    }
+
+    /// The TOML version to request, via [`SPEC_VERSION_ENV`][crate::SPEC_VERSION_ENV]
+    ///
+    /// See [`decoder_main`][crate::decoder_main] and [`encoder_main`][crate::encoder_main].
+    pub fn spec_version(mut self, version: crate::SpecVersion) -> Self {
+        self.spec_version = Some(version);
+        self.session = Default::default();
+        self
+    }
+
+    /// The tagged-JSON format the binary reads and writes, for older implementations
+    pub fn json_format(mut self, format: crate::JsonFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Kill the binary if it runs longer than `timeout` for one case
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Kill the binary if it writes more than `bytes` to either stdout or stderr for one case
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self.session = Default::default();
+        self
+    }
+
+    /// Limit the address space of the binary to `bytes`, via `RLIMIT_AS`
+    ///
+    /// This is only enforced on Linux.  Note that runtimes like the JVM reserve far more address
+    /// space than they use.
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self.session = Default::default();
+        self
+    }
+
+    /// Handle all cases in one long-running process, see [`BATCH_ENV`][crate::BATCH_ENV]
+    ///
+    /// Falls back to a process per case when the binary doesn't complete the handshake within
+    /// the [`timeout`][Command::timeout], or 10 seconds.  The process is restarted after it
+    /// crashes or exceeds a limit.
+    ///
+    /// Clones share the process, like the per-case clones made by `toml-test-harness`.  Changing
+    /// how the binary is started, like with [`Command::arg`], gives a clone its own process.
+    pub fn batch(mut self, yes: bool) -> Self {
+        self.batch = yes;
+        self
+    }
+
+    fn run(&self, op: Op, input: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        let input = self.stdin_encoding.encode(input)?;
+
+        if self.batch {
+            let spawn = || self.command().env(crate::BATCH_ENV, "1").spawn();
+            let result = self
+                .session
+                .request(&spawn, op, &input, self.timeout, self.max_output);
+            let failed =
+                |message| crate::Error::new(format!("{} failed: {message}", self.bin.display()));
+            match result {
+                Ok(output) => return Ok(output),
+                Err(BatchError::Unsupported) => {}
+                Err(BatchError::Failed(message, location)) => {
+                    return Err(with_location(failed(message), location));
+                }
+                Err(BatchError::Crashed(stderr)) => {
+                    return Err(
+                        failed(format!("exited early: {stderr}")).with_kind(ErrorKind::Panic)
+                    );
+                }
+                Err(BatchError::TimedOut) => return Err(self.timed_out()),
+                Err(BatchError::OutputExceeded) => return Err(self.output_exceeded()),
+            }
+        }
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut child = self.command().spawn().map_err(|err| {
+            crate::Error::io(format!("failed to run {}", self.bin.display()), err)
+                .with_kind(ErrorKind::Spawn)
+        })?;
+
+        // Pipes are serviced on their own threads so a binary that stops reading stdin or floods
+        // stdout can't block us from enforcing the limits
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let max_output = self
+            .max_output
+            .map(|bytes| bytes as u64)
+            .unwrap_or(u64::MAX);
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let stdout = read_capped(child.stdout.take().unwrap(), max_output, &exceeded);
+        let stderr = read_capped(child.stderr.take().unwrap(), max_output, &exceeded);
+
+        // Once the deadline passes, the pipe threads are detached as grandchildren may still hold
+        // the pipes open
+        let status = self.wait(&mut child, &exceeded, deadline)?;
+        let stdout = join_until(stdout, deadline)
+            .ok_or_else(|| self.timed_out())?
+            .map_err(|err| self.io_error("read stdout of", err))?;
+        let stderr = join_until(stderr, deadline)
+            .ok_or_else(|| self.timed_out())?
+            .map_err(|err| self.io_error("read stderr of", err))?;
+        if exceeded.load(Ordering::Relaxed) {
+            return Err(self.output_exceeded());
+        }
+        // A binary may exit without reading all of stdin, so it is judged by its exit status
+        match join_until(writer, deadline).ok_or_else(|| self.timed_out())? {
+            Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => {
+                return Err(self.io_error("write stdin of", err));
+            }
+            _ => {}
+        }
+
+        if status.success() {
+            Ok(stdout)
+        } else {
+            let stderr = String::from_utf8_lossy(&stderr);
+            let (message, location) = crate::bin::split_location(&stderr);
+            // Without an exit code, the binary was killed by a signal
+            let kind = match status.code() {
+                Some(_) => ErrorKind::Other,
+                None => ErrorKind::Panic,
+            };
+            let err = crate::Error::new(format!(
+                "{} failed with {:?}: {}",
+                self.bin.display(),
+                status.code(),
+                message
+            ))
+            .with_kind(kind);
+            Err(with_location(err, location))
+        }
+    }
+
+    fn command(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(&self.bin);
+        cmd.args(&self.args);
+        if let Some(version) = self.spec_version {
+            cmd.env(crate::SPEC_VERSION_ENV, version.as_str());
+        }
+        cmd.envs(self.envs.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
+        if let Some(bytes) = self.memory_limit {
+            limit_memory(&mut cmd, bytes);
+        }
+        cmd.stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        cmd
+    }
+
+    fn wait(
+        &self,
+        child: &mut std::process::Child,
+        exceeded: &AtomicBool,
+        deadline: Option<Instant>,
+    ) -> Result<std::process::ExitStatus, crate::Error> {
+        if deadline.is_none() && self.max_output.is_none() {
+            return child.wait().map_err(|err| self.io_error("wait for", err));
+        }
+
+        let mut interval = Duration::from_micros(100);
+        loop {
+            if let Some(status) = child
+                .try_wait()
+                .map_err(|err| self.io_error("wait for", err))?
+            {
+                return Ok(status);
+            }
+            let err = if exceeded.load(Ordering::Relaxed) {
+                self.output_exceeded()
+            } else if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                self.timed_out()
+            } else {
+                std::thread::sleep(interval);
+                interval = (interval * 2).min(Duration::from_millis(10));
+                continue;
+            };
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+    }
+
+    fn io_error(&self, action: &str, err: std::io::Error) -> crate::Error {
+        crate::Error::io(format!("failed to {action} {}", self.bin.display()), err)
+    }
+
+    fn timed_out(&self) -> crate::Error {
+        crate::Error::new(format!(
+            "{} timed out after {:?}",
+            self.bin.display(),
+            self.timeout.unwrap()
+        ))
+        .with_kind(ErrorKind::Timeout)
+    }
+
+    fn output_exceeded(&self) -> crate::Error {
+        crate::Error::new(format!(
+            "{} exceeded the output limit of {} bytes",
+            self.bin.display(),
+            self.max_output.unwrap()
+        ))
+        .with_kind(ErrorKind::Protocol)
+    }
+}
+
+impl Encoder for Command {
+    fn encode(&self, data: crate::decoded::DecodedValue) -> Result<String, crate::Error> {
+        let data = data.to_string_pretty_with_format(self.format)?;
+        let output = self.run(Op::Encode, data.as_bytes())?;
+        String::from_utf8(output).map_err(|err| {
+            crate::Error::new(format!(
+                "{} wrote TOML that is not UTF-8",
+                self.bin.display()
+            ))
+            .with_kind(ErrorKind::Protocol)
+            .with_source(err)
+        })
+    }
+
+    fn name(&self) -> &str {
+        self.bin.to_str().expect("we'll always get valid UTF-8")
+    }
+}
+
+impl Decoder for Command {
+    fn decode(&self, data: &[u8]) -> Result<crate::decoded::DecodedValue, crate::Error> {
+        let output = self.run(Op::Decode, data)?;
+        crate::decoded::DecodedValue::from_slice_with_format(&output, self.format)
+    }
+
+    fn name(&self) -> &str {
+        self.bin.to_str().expect("we'll always get valid UTF-8")
+    }
+}
+
+/// Read all of `pipe`, stopping and flagging `exceeded` after `max` bytes
+fn read_capped(
+    pipe: impl std::io::Read + Send + 'static,
+    max: u64,
+    exceeded: &Arc<AtomicBool>,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    let exceeded = exceeded.clone();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.take(max.saturating_add(1)).read_to_end(&mut buf)?;
+        if max < buf.len() as u64 {
+            exceeded.store(true, Ordering::Relaxed);
+        }
+        Ok(buf)
+    })
+}
+
+/// Attach the location reported by the binary, see [`decoder_main`][crate::decoder_main]
+fn with_location(err: crate::Error, location: Option<(usize, usize)>) -> crate::Error {
+    match location {
+        Some((line, column)) => err.with_line_column(line, column),
+        None => err,
+    }
+}
+
+/// Join `handle`, giving up and detaching it once `deadline` passes
+fn join_until<T>(handle: std::thread::JoinHandle<T>, deadline: Option<Instant>) -> Option<T> {
+    let mut interval = Duration::from_micros(100);
+    loop {
+        if deadline.is_none() || handle.is_finished() {
+            return Some(handle.join().unwrap());
+        }
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            return None;
+        }
+        std::thread::sleep(interval);
+        interval = (interval * 2).min(Duration::from_millis(10));
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn limit_memory(cmd: &mut std::process::Command, bytes: u64) {
+    use std::os::unix::process::CommandExt as _;
+
+    let limit = libc::rlimit {
+        rlim_cur: bytes as libc::rlim_t,
+        rlim_max: bytes as libc::rlim_t,
+    };
+    // SAFETY: `setrlimit` is async-signal-safe and the closure doesn't allocate
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn limit_memory(_cmd: &mut std::process::Command, _bytes: u64) {}
+
+/// How [`Command`] writes TOML and tagged JSON to the binary's stdin
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StdinEncoding {
+    /// The bytes, unchanged
+    #[default]
+    Raw,
+    /// UTF-16, little-endian, without a BOM
+    ///
+    /// Input that is not UTF-8, like some invalid cases, fails without running the binary.
+    Utf16Le,
+    /// UTF-16, big-endian, without a BOM
+    ///
+    /// Input that is not UTF-8, like some invalid cases, fails without running the binary.
+    Utf16Be,
+}
+
+impl StdinEncoding {
+    fn encode(self, input: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        let to_bytes = match self {
+            StdinEncoding::Raw => return Ok(input.to_owned()),
+            StdinEncoding::Utf16Le => u16::to_le_bytes,
+            StdinEncoding::Utf16Be => u16::to_be_bytes,
+        };
+        let input = std::str::from_utf8(input)
+            .map_err(|err| crate::Error::new(format!("cannot encode input as UTF-16: {err}")))?;
+        Ok(input.encode_utf16().flat_map(to_bytes).collect())
+    }
+}
+
+fn split_command_line(line: &str) -> Result<Vec<String>, crate::Error> {
+    let unterminated =
+        |quote: char| crate::Error::new(format!("unterminated `{quote}` in command line {line:?}"));
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next().ok_or_else(|| unterminated('\''))? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next().ok_or_else(|| unterminated('"'))? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(|| unterminated('"'))? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            '\n' => {}
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_default().push(c),
+                None => {
+                    return Err(crate::Error::new(format!(
+                        "trailing `\\` in command line {line:?}"
+                    )));
+                }
+            },
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_line() {
+        assert_eq!(
+            split_command_line(r#"  python3 -m  'tomllib shim' "a \"b\" \c" d\ e '' "#).unwrap(),
+            ["python3", "-m", "tomllib shim", r#"a "b" \c"#, "d e", ""]
+        );
+        assert_eq!(split_command_line("a'b'\"c\"").unwrap(), ["abc"]);
+        assert_eq!(
+            split_command_line("a 'b").unwrap_err().to_string(),
+            r#"unterminated `'` in command line "a 'b""#
+        );
+        assert_eq!(
+            split_command_line("a\\").unwrap_err().to_string(),
+            r#"trailing `\` in command line "a\\""#
+        );
+
+        let command =
+            Command::from_command_line("TOML_TEST_VERSION=1.1 A_1= decoder --decode B=1").unwrap();
+        let expected = Command::new("decoder")
+            .args(["--decode", "B=1"])
+            .env("TOML_TEST_VERSION", "1.1")
+            .env("A_1", "");
+        assert_eq!(command, expected);
+        assert_eq!(
+            Command::from_command_line(" A=1 ").unwrap_err().to_string(),
+            r#"no program in " A=1 ""#
+        );
+    }
+
+    #[test]
+    fn stdin_encoding() {
+        assert_eq!(StdinEncoding::Raw.encode(b"\xff").unwrap(), b"\xff");
+        assert_eq!(
+            StdinEncoding::Utf16Le
+                .encode("a\u{1F600}".as_bytes())
+                .unwrap(),
+            [0x61, 0x00, 0x3D, 0xD8, 0x00, 0xDE]
+        );
+        assert_eq!(
+            StdinEncoding::Utf16Be
+                .encode("a\u{1F600}".as_bytes())
+                .unwrap(),
+            [0x00, 0x61, 0xD8, 0x3D, 0xDE, 0x00]
+        );
+        assert!(StdinEncoding::Utf16Le.encode(b"\xff").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run() {
+        let decoder = Command::new("sh")
+            .arg("-c")
+            .arg(
+                r#"cat > /dev/null; printf '{"a": {"type": "string", "value": "%s %s %s"}}' "$1" "$A" "$PWD""#,
+            )
+            .arg("sh")
+            .arg("arg")
+            .env("A", "env")
+            .current_dir("/");
+        assert_eq!(
+            decoder.decode(b"").unwrap(),
+            crate::decoded!({ "a": string("arg env /") })
+        );
+
+        let err = Command::from_command_line("sh -c 'cat > /dev/null; echo oops >&2; exit 3'")
+            .unwrap()
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sh failed with Some(3): oops\n");
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.line(), None);
+
+        let decoder = Command::from_command_line(
+            "sh -c 'cat > /dev/null; printf \"oops\\ntoml-test location: 2:5\\n\" >&2; exit 1'",
+        )
+        .unwrap();
+        let err = decoder
+            .verify_invalid_case_at(b"a = 1\nb = ", 2, 5)
+            .unwrap();
+        assert_eq!(err.to_string(), "sh failed with Some(1): oops\n");
+
+        let err = Command::from_command_line("sh -c 'cat > /dev/null; kill -9 $$'")
+            .unwrap()
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Panic);
+
+        let err = Command::from_command_line("sh -c 'cat > /dev/null; echo oops'")
+            .unwrap()
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Protocol);
+
+        // Not reading stdin is fine, it's the exit status that counts
+        let input = vec![b'#'; 1 << 20];
+        let decoder = Command::from_command_line("sh -c 'echo {}'").unwrap();
+        assert_eq!(decoder.decode(&input).unwrap(), crate::decoded!({}));
+        let err = Command::from_command_line("sh -c 'echo oops >&2; exit 3'")
+            .unwrap()
+            .decode(&input)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sh failed with Some(3): oops\n");
+        assert_eq!(err.kind(), ErrorKind::Other);
+
+        let err = Command::new("/nonexistent/toml-test-decoder")
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Spawn);
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn limits() {
+        let start = Instant::now();
+        let err = Command::from_command_line("sh -c 'sleep 10'")
+            .unwrap()
+            .timeout(Duration::from_millis(100))
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sh timed out after 100ms");
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // A grandchild holding the pipes open can't outlast the timeout
+        let start = Instant::now();
+        let err = Command::from_command_line("sh -c 'sleep 10 & echo {}'")
+            .unwrap()
+            .timeout(Duration::from_millis(100))
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let err = Command::from_command_line("sh -c 'cat > /dev/null; yes'")
+            .unwrap()
+            .max_output(1024)
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sh exceeded the output limit of 1024 bytes"
+        );
+
+        let err = Command::from_command_line("sh -c 'cat > /dev/null; echo oops >&2'")
+            .unwrap()
+            .max_output(2)
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sh exceeded the output limit of 2 bytes");
+        assert_eq!(err.kind(), ErrorKind::Protocol);
+
+        let decoder = Command::from_command_line("sh -c 'cat > /dev/null; echo {}'")
+            .unwrap()
+            .timeout(Duration::from_secs(60))
+            .max_output(3);
+        assert_eq!(decoder.decode(b"").unwrap(), crate::decoded!({}));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn batch() {
+        // Replies with its PID, or fails on empty and one byte input
+        let server = r#"
+echo 'toml-test batch 1'
+while read -r op len; do
+    dd bs=1 count="$len" of=/dev/null 2> /dev/null
+    if [ "$len" = 0 ]; then
+        printf 'err 5\nempty'
+    elif [ "$len" = 1 ]; then
+        printf 'err 3 1:2\nbad'
+    else
+        body="{\"pid\": {\"type\": \"integer\", \"value\": \"$$\"}}"
+        printf 'ok %d\n%s' "${#body}" "$body"
+    fi
+done
+"#;
+        let decoder = Command::new("sh")
+            .args(["-c", server])
+            .timeout(Duration::from_secs(60))
+            .batch(true);
+        let first = decoder.decode(b"a = 1").unwrap();
+        assert_eq!(decoder.decode(b"b = 2").unwrap(), first);
+        let err = decoder.decode(b"").unwrap_err();
+        assert_eq!(err.to_string(), "sh failed: empty");
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.line(), None);
+        let err = decoder.verify_invalid_case_at(b"x", 1, 2).unwrap();
+        assert_eq!(err.to_string(), "sh failed: bad");
+        assert_eq!(decoder.decode(b"c = 3").unwrap(), first);
+        assert_eq!(decoder.clone().decode(b"a = 1").unwrap(), first);
+        assert_ne!(
+            decoder.clone().env("A", "1").decode(b"a = 1").unwrap(),
+            first
+        );
+
+        // Flooding stderr fails like it does without batching
+        let server = r#"
+echo 'toml-test batch 1'
+read -r op len
+head -c 1000000 /dev/zero >&2
+printf 'ok 2\n{}'
+"#;
+        let err = Command::new("sh")
+            .args(["-c", server])
+            .timeout(Duration::from_secs(60))
+            .max_output(1024)
+            .batch(true)
+            .decode(b"")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sh exceeded the output limit of 1024 bytes"
+        );
+        assert_eq!(err.kind(), ErrorKind::Protocol);
+
+        let decoder = Command::from_command_line("sh -c 'cat > /dev/null; echo {}'")
+            .unwrap()
+            .timeout(Duration::from_millis(200))
+            .batch(true);
+        assert_eq!(decoder.decode(b"").unwrap(), crate::decoded!({}));
+        assert_eq!(decoder.decode(b"").unwrap(), crate::decoded!({}));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn memory_limit() {
+        let decoder = Command::from_command_line("sh -c 'cat > /dev/null; echo {}'").unwrap();
+        assert!(decoder.clone().memory_limit(1 << 20).decode(b"").is_err());
+        assert!(decoder.memory_limit(1 << 32).decode(b"").is_ok());
+    }
+}
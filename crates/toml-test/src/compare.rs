@@ -0,0 +1,898 @@
+use crate::SpecVersion;
+use crate::decoded::ScalarType;
+
+/// How strictly to compare [`DecodedScalar`][crate::DecodedScalar]s
+///
+/// The [`Default`] matches [`PartialEq`]:
+/// - Datetimes are equal when they refer to the same instant
+/// - Floats are compared exactly, `-0.0 == 0.0` and the sign of NaN is ignored
+/// - Fractional seconds are compared to nanosecond precision
+/// - The lexical forms of every [`SpecVersion`] are accepted, e.g. times without seconds
+///
+/// # Example
+///
+/// ```rust
+/// let policy = toml_test::ComparePolicy::new()
+///     .strict_offset(true)
+///     .float_tolerance(1e-9);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComparePolicy {
+    strict_offset: bool,
+    signed_zero: bool,
+    nan_sign: bool,
+    fractional_digits: u32,
+    float_tolerance: f64,
+    spec_version: Option<SpecVersion>,
+}
+
+impl ComparePolicy {
+    pub const fn new() -> Self {
+        Self {
+            strict_offset: false,
+            signed_zero: false,
+            nan_sign: false,
+            fractional_digits: 9,
+            float_tolerance: 0.0,
+            spec_version: None,
+        }
+    }
+
+    /// Require datetimes to have the same offset, not just refer to the same instant
+    pub const fn strict_offset(mut self, yes: bool) -> Self {
+        self.strict_offset = yes;
+        self
+    }
+
+    /// Treat `-0.0` and `0.0` as different
+    pub const fn signed_zero(mut self, yes: bool) -> Self {
+        self.signed_zero = yes;
+        self
+    }
+
+    /// Treat `-nan` and `nan` as different
+    pub const fn nan_sign(mut self, yes: bool) -> Self {
+        self.nan_sign = yes;
+        self
+    }
+
+    /// Truncate fractional seconds to `digits` before comparing
+    ///
+    /// TOML requires at least millisecond precision (`3`) and for extra precision to be
+    /// truncated, not rounded.  Values above `9` (nanoseconds) have no effect.
+    pub const fn fractional_digits(mut self, digits: u32) -> Self {
+        self.fractional_digits = digits;
+        self
+    }
+
+    /// Treat floats as equal when within this relative tolerance of each other
+    pub const fn float_tolerance(mut self, tolerance: f64) -> Self {
+        self.float_tolerance = tolerance;
+        self
+    }
+
+    /// Only accept the lexical forms of `version`
+    ///
+    /// For example, times without seconds are unparseable before TOML 1.1.
+    pub const fn spec_version(mut self, version: SpecVersion) -> Self {
+        self.spec_version = Some(version);
+        self
+    }
+
+    fn truncate_nanos(&self, nanos: u32) -> u32 {
+        if 9 <= self.fractional_digits {
+            nanos
+        } else {
+            let unit = 10u32.pow(9 - self.fractional_digits);
+            nanos - nanos % unit
+        }
+    }
+
+    fn float_eq(&self, expected: &str, e: f64, actual: &str, a: f64) -> bool {
+        if e.is_nan() || a.is_nan() {
+            e.is_nan()
+                && a.is_nan()
+                && (!self.nan_sign || is_negative(expected) == is_negative(actual))
+        } else if e == a {
+            !self.signed_zero || e.is_sign_negative() == a.is_sign_negative()
+        } else if e.is_infinite() || a.is_infinite() {
+            false
+        } else {
+            (e - a).abs() <= self.float_tolerance * e.abs().max(a.abs())
+        }
+    }
+}
+
+impl Default for ComparePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_negative(float: &str) -> bool {
+    float.starts_with('-')
+}
+
+/// Check that `s` is lexically valid for `ty`
+pub(crate) fn validate_scalar(ty: ScalarType, s: &str) -> Result<(), String> {
+    match ty {
+        ScalarType::String => Ok(()),
+        ScalarType::Integer => parse_integer(s).map(|_| ()),
+        ScalarType::Float => parse_float(s).map(|_| ()),
+        ScalarType::Bool => match s {
+            "true" | "false" => Ok(()),
+            _ => Err(format!("invalid bool {s:?}")),
+        },
+        ScalarType::Datetime => parse_date_time(s).map(|_| ()),
+        ScalarType::DatetimeLocal => parse_date_time_local(s).map(|_| ()),
+        ScalarType::DateLocal => parse_date_local(s).map(|_| ()),
+        ScalarType::TimeLocal => parse_time_local(s).map(|_| ()),
+    }
+}
+
+/// Rewrite the text of a scalar into its canonical spelling, see
+/// [`DecodedScalar::normalized`][crate::DecodedScalar::normalized]
+pub(crate) fn normalize_scalar(ty: ScalarType, s: &str) -> Result<String, String> {
+    match ty {
+        ScalarType::String => Ok(s.to_owned()),
+        ScalarType::Integer => parse_integer(s).map(|i| i.to_string()),
+        ScalarType::Float => {
+            let float = parse_float(s)?;
+            let sign = if is_negative(s) { "-" } else { "" };
+            let normalized = if float.is_nan() {
+                format!("{sign}nan")
+            } else {
+                crate::DecodedScalar::from(float).as_str().to_owned()
+            };
+            Ok(normalized)
+        }
+        ScalarType::Bool => validate_scalar(ty, s).map(|()| s.to_owned()),
+        ScalarType::Datetime
+        | ScalarType::DatetimeLocal
+        | ScalarType::DateLocal
+        | ScalarType::TimeLocal => {
+            let normalized = complete_datetime(s);
+            validate_scalar(ty, &normalized)?;
+            Ok(trim_fraction(normalized))
+        }
+    }
+}
+
+/// Add the seconds to a time that omits them, as allowed in TOML 1.1
+///
+/// The seconds may only be omitted when nothing or an offset follows the minutes.
+pub(crate) fn with_seconds(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let has_date = 10 <= bytes.len() && bytes[4] == b'-' && bytes[7] == b'-';
+    let time_start = if has_date { 11 } else { 0 };
+    let time = bytes.get(time_start..)?;
+    let secondless = 5 <= time.len()
+        && time[..5].iter().enumerate().all(|(i, b)| {
+            if i == 2 {
+                *b == b':'
+            } else {
+                b.is_ascii_digit()
+            }
+        })
+        && matches!(time.get(5), None | Some(b'Z' | b'z' | b'+' | b'-'));
+    secondless.then(|| {
+        let insert = time_start + 5;
+        format!("{}:00{}", &value[..insert], &value[insert..])
+    })
+}
+
+/// Drop trailing zeros from fractional seconds, and the `.` if nothing is left
+fn trim_fraction(mut value: String) -> String {
+    let Some(dot) = value.find('.') else {
+        return value;
+    };
+    let digits = value[dot + 1..]
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len() - dot - 1);
+    let fraction = &value[dot + 1..dot + 1 + digits];
+    let kept = fraction.trim_end_matches('0').len();
+    let remove_start = if kept == 0 { dot } else { dot + 1 + kept };
+    value.replace_range(remove_start..dot + 1 + digits, "");
+    value
+}
+
+/// What [`PartialEq`] for [`DecodedScalar`][crate::DecodedScalar] compares, for implementing
+/// [`Hash`] and [`Ord`] consistently with it
+///
+/// Values that fail to parse, or integers out of range, are only equal to the exact same text.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum ScalarKey<'s> {
+    Integer(i64),
+    Float(FloatKey),
+    /// The instant, in UTC
+    Datetime(chrono::NaiveDateTime),
+    DatetimeLocal(chrono::NaiveDateTime),
+    DateLocal(chrono::NaiveDate),
+    TimeLocal(chrono::NaiveTime),
+    Text(&'s str),
+}
+
+impl<'s> ScalarKey<'s> {
+    pub(crate) fn new(ty: ScalarType, s: &'s str) -> Self {
+        let key = match ty {
+            ScalarType::String | ScalarType::Bool => None,
+            ScalarType::Integer => parse_integer(s)
+                .ok()
+                .and_then(|i| i.to_i64())
+                .map(Self::Integer),
+            ScalarType::Float => parse_float(s).ok().map(|f| Self::Float(FloatKey::new(f))),
+            ScalarType::Datetime => parse_date_time(s)
+                .ok()
+                .map(|dt| Self::Datetime(dt.naive_utc())),
+            ScalarType::DatetimeLocal => parse_date_time_local(s).ok().map(Self::DatetimeLocal),
+            ScalarType::DateLocal => parse_date_local(s).ok().map(Self::DateLocal),
+            ScalarType::TimeLocal => parse_time_local(s).ok().map(Self::TimeLocal),
+        };
+        key.unwrap_or(Self::Text(s))
+    }
+}
+
+/// A float with `-0.0 == 0.0` and a single NaN, ordered with NaN last
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FloatKey(f64);
+
+impl FloatKey {
+    fn new(float: f64) -> Self {
+        if float.is_nan() {
+            Self(f64::NAN)
+        } else if float == 0.0 {
+            Self(0.0)
+        } else {
+            Self(float)
+        }
+    }
+}
+
+impl PartialEq for FloatKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for FloatKey {}
+
+impl PartialOrd for FloatKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloatKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl std::hash::Hash for FloatKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// Compare the text of two scalars of the same type
+///
+/// Identical text is always equal, keeping [`Eq`] reflexive for values [`scalar_cmp`] rejects,
+/// like out of range integers.
+pub(crate) fn scalar_eq(ty: ScalarType, s: &str, o: &str) -> bool {
+    s == o || scalar_cmp(ty, s, o, &ComparePolicy::new()).is_ok()
+}
+
+/// Compare the text of an `expected` and `actual` scalar of the same type
+pub(crate) fn scalar_cmp(
+    ty: ScalarType,
+    expected: &str,
+    actual: &str,
+    policy: &ComparePolicy,
+) -> Result<(), ScalarMismatch> {
+    if let Some(version) = policy.spec_version {
+        check_version(ty, expected, version).map_err(ScalarMismatch::UnparseableExpected)?;
+        check_version(ty, actual, version).map_err(ScalarMismatch::UnparseableActual)?;
+    }
+    // Integers are range checked even when their text is identical
+    if expected == actual && ty != ScalarType::Integer {
+        return Ok(());
+    }
+    match ty {
+        ScalarType::String => Err(ScalarMismatch::DifferentValue),
+        ScalarType::Integer => {
+            let (e, a) = parse_both(ty, expected, actual, parse_integer)?;
+            if !e.fits_i64() {
+                Err(ScalarMismatch::OutOfRangeExpected(expected.to_owned()))
+            } else if !a.fits_i64() {
+                Err(ScalarMismatch::OutOfRangeActual(actual.to_owned()))
+            } else {
+                check_value(e == a)
+            }
+        }
+        ScalarType::Bool => {
+            parse_both(ty, expected, actual, |s| Ok(s.to_owned()))?;
+            Err(ScalarMismatch::DifferentValue)
+        }
+        ScalarType::Float => {
+            let (e, a) = parse_both(ty, expected, actual, parse_float)?;
+            check_value(policy.float_eq(expected, e, actual, a))
+        }
+        ScalarType::Datetime => {
+            let (e, a) = parse_both(ty, expected, actual, parse_date_time)?;
+            let (e, a) = (
+                with_nanos(e, policy.truncate_nanos(chrono::Timelike::nanosecond(&e))),
+                with_nanos(a, policy.truncate_nanos(chrono::Timelike::nanosecond(&a))),
+            );
+            if e != a {
+                Err(ScalarMismatch::DifferentInstant)
+            } else if policy.strict_offset && e.offset() != a.offset() {
+                Err(ScalarMismatch::DifferentOffset)
+            } else {
+                Ok(())
+            }
+        }
+        ScalarType::DatetimeLocal => {
+            let (e, a) = parse_both(ty, expected, actual, parse_date_time_local)?;
+            let e = with_nanos(e, policy.truncate_nanos(chrono::Timelike::nanosecond(&e)));
+            let a = with_nanos(a, policy.truncate_nanos(chrono::Timelike::nanosecond(&a)));
+            check_value(e == a)
+        }
+        ScalarType::DateLocal => {
+            let (e, a) = parse_both(ty, expected, actual, parse_date_local)?;
+            check_value(e == a)
+        }
+        ScalarType::TimeLocal => {
+            let (e, a) = parse_both(ty, expected, actual, parse_time_local)?;
+            let e = with_nanos(e, policy.truncate_nanos(chrono::Timelike::nanosecond(&e)));
+            let a = with_nanos(a, policy.truncate_nanos(chrono::Timelike::nanosecond(&a)));
+            check_value(e == a)
+        }
+    }
+}
+
+/// Reject lexical forms that `version` does not support
+fn check_version(ty: ScalarType, s: &str, version: SpecVersion) -> Result<(), String> {
+    let has_time = matches!(
+        ty,
+        ScalarType::Datetime | ScalarType::DatetimeLocal | ScalarType::TimeLocal
+    );
+    if has_time && version < SpecVersion::V1_1_0 && with_seconds(s).is_some() {
+        return Err(format!(
+            "invalid {} {s:?}: seconds are required before TOML 1.1",
+            ty.name()
+        ));
+    }
+    Ok(())
+}
+
+fn with_nanos<T: chrono::Timelike>(value: T, nanos: u32) -> T {
+    value
+        .with_nanosecond(nanos)
+        .expect("truncating never goes out of range")
+}
+
+fn check_value(equal: bool) -> Result<(), ScalarMismatch> {
+    if equal {
+        Ok(())
+    } else {
+        Err(ScalarMismatch::DifferentValue)
+    }
+}
+
+fn parse_both<T>(
+    ty: ScalarType,
+    expected: &str,
+    actual: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<(T, T), ScalarMismatch> {
+    let validate = |s: &str| match ty {
+        ScalarType::Bool if s != "true" && s != "false" => Err(format!("invalid bool {s:?}")),
+        _ => parse(s),
+    };
+    let expected = validate(expected).map_err(ScalarMismatch::UnparseableExpected)?;
+    let actual = validate(actual).map_err(ScalarMismatch::UnparseableActual)?;
+    Ok((expected, actual))
+}
+
+/// Arbitrary precision decimal integer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BigInt {
+    negative: bool,
+    /// Without leading zeros, empty for `0`
+    digits: String,
+}
+
+impl BigInt {
+    fn fits_i64(&self) -> bool {
+        const MAX: &str = "9223372036854775807";
+        const MIN: &str = "9223372036854775808";
+        let limit = if self.negative { MIN } else { MAX };
+        self.digits.len() < limit.len()
+            || (self.digits.len() == limit.len() && self.digits.as_str() <= limit)
+    }
+
+    pub(crate) fn to_i64(&self) -> Option<i64> {
+        if !self.fits_i64() {
+            return None;
+        }
+        self.to_string().parse().ok()
+    }
+}
+
+impl std::fmt::Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        let digits = if self.digits.is_empty() {
+            "0"
+        } else {
+            &self.digits
+        };
+        write!(f, "{sign}{digits}")
+    }
+}
+
+/// Parse a decimal integer, allowing a `+` sign, leading zeros, and `_` between digits
+pub(crate) fn parse_integer(s: &str) -> Result<BigInt, String> {
+    let invalid = || format!("invalid integer {s:?}");
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if unsigned.is_empty() || unsigned.starts_with('_') || unsigned.ends_with('_') {
+        return Err(invalid());
+    }
+    let mut digits = String::with_capacity(unsigned.len());
+    let mut prev_underscore = false;
+    for c in unsigned.chars() {
+        match c {
+            '_' if prev_underscore => return Err(invalid()),
+            '_' => prev_underscore = true,
+            '0' if digits.is_empty() => prev_underscore = false,
+            '0'..='9' => {
+                prev_underscore = false;
+                digits.push(c);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    let negative = negative && !digits.is_empty();
+    Ok(BigInt { negative, digits })
+}
+
+pub(crate) fn parse_float(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .map_err(|err| format!("invalid float {s:?}: {err}"))
+}
+
+fn parse_date_time(s: &str) -> Result<chrono::DateTime<chrono::FixedOffset>, String> {
+    let value = complete_datetime(s)
+        .parse()
+        .map_err(|err| format!("invalid datetime {s:?}: {err}"))?;
+    check_lexical(ScalarType::Datetime, s)?;
+    Ok(value)
+}
+
+fn parse_date_time_local(s: &str) -> Result<chrono::NaiveDateTime, String> {
+    let value = complete_datetime(s)
+        .parse()
+        .map_err(|err| format!("invalid datetime-local {s:?}: {err}"))?;
+    check_lexical(ScalarType::DatetimeLocal, s)?;
+    Ok(value)
+}
+
+fn parse_date_local(s: &str) -> Result<chrono::NaiveDate, String> {
+    let value = s
+        .parse()
+        .map_err(|err| format!("invalid date-local {s:?}: {err}"))?;
+    check_lexical(ScalarType::DateLocal, s)?;
+    Ok(value)
+}
+
+fn parse_time_local(s: &str) -> Result<chrono::NaiveTime, String> {
+    let value = complete_datetime(s)
+        .parse()
+        .map_err(|err| format!("invalid time-local {s:?}: {err}"))?;
+    check_lexical(ScalarType::TimeLocal, s)?;
+    Ok(value)
+}
+
+/// Check `s` has the shape of a TOML 1.1 `ty`, which chrono is more lenient about
+///
+/// For example, `date-time = full-date T partial-time time-offset`, with
+/// `partial-time = HH:MM[:SS[.fraction]]` and `time-offset = Z / (+ / -)HH:MM`.
+fn check_lexical(ty: ScalarType, s: &str) -> Result<(), String> {
+    fn digits(b: &[u8], n: usize) -> Option<&[u8]> {
+        let rest = b.get(n..)?;
+        b[..n].iter().all(u8::is_ascii_digit).then_some(rest)
+    }
+    fn sep(b: &[u8], c: u8) -> Option<&[u8]> {
+        (b.first() == Some(&c)).then(|| &b[1..])
+    }
+    fn date(b: &[u8]) -> Option<&[u8]> {
+        let b = sep(digits(b, 4)?, b'-')?;
+        digits(sep(digits(b, 2)?, b'-')?, 2)
+    }
+    fn time(b: &[u8]) -> Option<&[u8]> {
+        let b = digits(sep(digits(b, 2)?, b':')?, 2)?;
+        let Some(b) = sep(b, b':') else {
+            return Some(b);
+        };
+        let b = digits(b, 2)?;
+        let Some(b) = sep(b, b'.') else {
+            return Some(b);
+        };
+        let len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+        (0 < len).then(|| &b[len..])
+    }
+    fn offset(b: &[u8]) -> Option<&[u8]> {
+        match b.first()? {
+            b'Z' | b'z' => Some(&b[1..]),
+            b'+' | b'-' => digits(sep(digits(&b[1..], 2)?, b':')?, 2),
+            _ => None,
+        }
+    }
+    fn date_time(b: &[u8]) -> Option<&[u8]> {
+        let b = date(b)?;
+        match b.first()? {
+            b'T' | b't' | b' ' => time(&b[1..]),
+            _ => None,
+        }
+    }
+
+    let b = s.as_bytes();
+    let rest = match ty {
+        ScalarType::Datetime => date_time(b).and_then(offset),
+        ScalarType::DatetimeLocal => date_time(b),
+        ScalarType::DateLocal => date(b),
+        ScalarType::TimeLocal => time(b),
+        _ => Some(&b[b.len()..]),
+    };
+    if rest.is_some_and(|rest| rest.is_empty()) {
+        Ok(())
+    } else {
+        Err(format!("invalid {} {s:?}: not in TOML format", ty.name()))
+    }
+}
+
+/// Rewrite any TOML spelling of a datetime into one chrono can parse
+fn complete_datetime(s: &str) -> String {
+    let normalized = normalize_datetime(s);
+    with_seconds(&normalized).unwrap_or(normalized)
+}
+
+fn normalize_datetime(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ' ' => 'T',
+            't' => 'T',
+            'z' => 'Z',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Why two [`DecodedScalar`][crate::DecodedScalar]s are not equal, see
+/// [`DecodedScalar::compare`][crate::DecodedScalar::compare]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScalarMismatch {
+    /// The scalars have different `type`s
+    DifferentType {
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// The expected value is not valid for its type
+    UnparseableExpected(String),
+    /// The actual value is not valid for its type
+    UnparseableActual(String),
+    /// The expected integer does not fit in the range TOML requires (64-bit signed)
+    OutOfRangeExpected(String),
+    /// The actual integer does not fit in the range TOML requires (64-bit signed)
+    OutOfRangeActual(String),
+    /// The values are different
+    DifferentValue,
+    /// The datetimes refer to different instants in time
+    DifferentInstant,
+    /// The datetimes refer to the same instant but with different offsets
+    ///
+    /// Only reported with [`ComparePolicy::strict_offset`]
+    DifferentOffset,
+}
+
+impl std::fmt::Display for ScalarMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScalarMismatch::DifferentType { expected, actual } => {
+                write!(f, "expected {expected}, found {actual}")
+            }
+            ScalarMismatch::UnparseableExpected(reason) => {
+                write!(f, "unparseable expected value: {reason}")
+            }
+            ScalarMismatch::UnparseableActual(reason) => {
+                write!(f, "unparseable actual value: {reason}")
+            }
+            ScalarMismatch::OutOfRangeExpected(value) => {
+                write!(f, "expected integer {value} is out of range")
+            }
+            ScalarMismatch::OutOfRangeActual(value) => {
+                write!(f, "actual integer {value} is out of range")
+            }
+            ScalarMismatch::DifferentValue => f.write_str("different value"),
+            ScalarMismatch::DifferentInstant => f.write_str("different instant"),
+            ScalarMismatch::DifferentOffset => f.write_str("different offset"),
+        }
+    }
+}
+
+impl std::error::Error for ScalarMismatch {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::DecodedScalar;
+
+    fn float(s: &str) -> DecodedScalar {
+        DecodedScalar::Float(s.to_owned())
+    }
+
+    #[test]
+    fn default_matches_eq() {
+        let policy = ComparePolicy::default();
+        assert_eq!(float("-0.0").compare_with(&float("0.0"), &policy), Ok(()));
+        assert_eq!(float("-nan").compare_with(&float("nan"), &policy), Ok(()));
+        assert_eq!(
+            DecodedScalar::Datetime("1987-07-05T17:45:00Z".to_owned()).compare_with(
+                &DecodedScalar::Datetime("1987-07-05T18:45:00+01:00".to_owned()),
+                &policy
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn strict_offset() {
+        let policy = ComparePolicy::new().strict_offset(true);
+        let utc = DecodedScalar::Datetime("1987-07-05T17:45:00Z".to_owned());
+        assert_eq!(
+            utc.compare_with(
+                &DecodedScalar::Datetime("1987-07-05T18:45:00+01:00".to_owned()),
+                &policy
+            ),
+            Err(ScalarMismatch::DifferentOffset)
+        );
+        assert_eq!(
+            utc.compare_with(
+                &DecodedScalar::Datetime("1987-07-05 17:45:00+00:00".to_owned()),
+                &policy
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            utc.compare_with(
+                &DecodedScalar::Datetime("1987-07-05T19:45:00+01:00".to_owned()),
+                &policy
+            ),
+            Err(ScalarMismatch::DifferentInstant)
+        );
+    }
+
+    #[test]
+    fn signed_zero() {
+        let policy = ComparePolicy::new().signed_zero(true);
+        assert_eq!(
+            float("-0.0").compare_with(&float("0.0"), &policy),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        assert_eq!(float("+0.0").compare_with(&float("0.0"), &policy), Ok(()));
+    }
+
+    #[test]
+    fn nan_sign() {
+        let policy = ComparePolicy::new().nan_sign(true);
+        assert_eq!(
+            float("-nan").compare_with(&float("nan"), &policy),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        assert_eq!(float("+nan").compare_with(&float("nan"), &policy), Ok(()));
+    }
+
+    #[test]
+    fn fractional_digits() {
+        let expected = DecodedScalar::TimeLocal("17:45:00.123456789".to_owned());
+        let actual = DecodedScalar::TimeLocal("17:45:00.123".to_owned());
+        assert_eq!(
+            expected.compare_with(&actual, &ComparePolicy::new()),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        assert_eq!(
+            expected.compare_with(&actual, &ComparePolicy::new().fractional_digits(3)),
+            Ok(())
+        );
+
+        let expected = DecodedScalar::DatetimeLocal("1987-07-05T17:45:00.999".to_owned());
+        let actual = DecodedScalar::DatetimeLocal("1987-07-05T17:45:00".to_owned());
+        assert_eq!(
+            expected.compare_with(&actual, &ComparePolicy::new().fractional_digits(0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn integer_semantics() {
+        let one = DecodedScalar::from(1);
+        for same in ["+1", "01", "+0_1"] {
+            assert_eq!(
+                one.compare(&DecodedScalar::Integer(same.to_owned())),
+                Ok(())
+            );
+        }
+        assert_eq!(
+            DecodedScalar::from(1000).compare(&DecodedScalar::Integer("1_000".to_owned())),
+            Ok(())
+        );
+        assert_eq!(
+            DecodedScalar::from(0).compare(&DecodedScalar::Integer("-0".to_owned())),
+            Ok(())
+        );
+        assert_eq!(
+            one.compare(&DecodedScalar::Integer("-1".to_owned())),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        for invalid in ["", "-", "1_", "_1", "1__0", "0x10", "1.0", "1e3"] {
+            assert!(
+                matches!(
+                    one.compare(&DecodedScalar::Integer(invalid.to_owned())),
+                    Err(ScalarMismatch::UnparseableActual(_))
+                ),
+                "{invalid:?} should be unparseable"
+            );
+        }
+    }
+
+    #[test]
+    fn integer_range() {
+        let max = DecodedScalar::from(i64::MAX);
+        let min = DecodedScalar::from(i64::MIN);
+        assert_eq!(
+            max.compare(&DecodedScalar::Integer("009223372036854775807".to_owned())),
+            Ok(())
+        );
+        assert_eq!(
+            min.compare(&DecodedScalar::Integer(
+                "-9_223_372_036_854_775_808".to_owned()
+            )),
+            Ok(())
+        );
+        assert_eq!(
+            max.compare(&DecodedScalar::Integer("9223372036854775808".to_owned())),
+            Err(ScalarMismatch::OutOfRangeActual(
+                "9223372036854775808".to_owned()
+            ))
+        );
+        assert_eq!(
+            min.compare(&DecodedScalar::Integer("-9223372036854775809".to_owned())),
+            Err(ScalarMismatch::OutOfRangeActual(
+                "-9223372036854775809".to_owned()
+            ))
+        );
+        assert_eq!(
+            DecodedScalar::Integer("100000000000000000000".to_owned()).compare(&max),
+            Err(ScalarMismatch::OutOfRangeExpected(
+                "100000000000000000000".to_owned()
+            ))
+        );
+        let huge = DecodedScalar::Integer("100000000000000000000".to_owned());
+        assert_eq!(huge, huge);
+        assert_eq!(
+            huge.compare(&huge),
+            Err(ScalarMismatch::OutOfRangeExpected(
+                "100000000000000000000".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn float_tolerance() {
+        let policy = ComparePolicy::new().float_tolerance(1e-9);
+        assert_eq!(
+            float("0.1").compare_with(&float("0.1000000000001"), &policy),
+            Ok(())
+        );
+        assert_eq!(
+            float("0.1").compare_with(&float("0.1001"), &policy),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        assert_eq!(
+            float("inf").compare_with(&float("1e308"), &policy),
+            Err(ScalarMismatch::DifferentValue)
+        );
+    }
+
+    #[test]
+    fn secondless_times() {
+        let equal = [
+            (ScalarType::TimeLocal, "07:32", "07:32:00"),
+            (
+                ScalarType::DatetimeLocal,
+                "1979-05-27T07:32",
+                "1979-05-27T07:32:00",
+            ),
+            (
+                ScalarType::DatetimeLocal,
+                "1979-05-27 07:32",
+                "1979-05-27t07:32:00.000",
+            ),
+            (
+                ScalarType::Datetime,
+                "1979-05-27T07:32Z",
+                "1979-05-27T07:32:00z",
+            ),
+            (
+                ScalarType::Datetime,
+                "1979-05-27T07:32-07:00",
+                "1979-05-27T14:32:00Z",
+            ),
+        ];
+        for (ty, expected, actual) in equal {
+            let (expected, actual) = (ty.scalar(expected.to_owned()), ty.scalar(actual.to_owned()));
+            assert_eq!(expected.compare(&actual), Ok(()), "{expected:?} {actual:?}");
+            assert_eq!(actual.compare(&expected), Ok(()), "{actual:?} {expected:?}");
+        }
+
+        let v1_0 = ComparePolicy::new().spec_version(SpecVersion::V1_0_0);
+        let v1_1 = ComparePolicy::new().spec_version(SpecVersion::V1_1_0);
+        for (ty, expected, actual) in equal {
+            let (expected, actual) = (ty.scalar(expected.to_owned()), ty.scalar(actual.to_owned()));
+            assert_eq!(expected.compare_with(&actual, &v1_1), Ok(()));
+            assert!(matches!(
+                expected.compare_with(&actual, &v1_0),
+                Err(ScalarMismatch::UnparseableExpected(_))
+            ));
+            assert!(matches!(
+                actual.compare_with(&expected, &v1_0),
+                Err(ScalarMismatch::UnparseableActual(_))
+            ));
+        }
+        let time = DecodedScalar::TimeLocal("07:32".to_owned());
+        assert!(matches!(
+            time.compare_with(&time, &v1_0),
+            Err(ScalarMismatch::UnparseableExpected(_))
+        ));
+    }
+
+    #[test]
+    fn malformed_times() {
+        for s in [
+            "07:32.5",
+            "07:3",
+            "7:32",
+            "07:32:",
+            "07:€2",
+            "07:32€",
+            "1979-05-27T07:32.5Z",
+            "1979-05-27T€",
+            "1979-05-27T07:€",
+            "1979-05-27T07:32:60:00",
+            "",
+        ] {
+            for ty in [
+                ScalarType::Datetime,
+                ScalarType::DatetimeLocal,
+                ScalarType::TimeLocal,
+            ] {
+                let scalar = ty.scalar(s.to_owned());
+                let other = ty.scalar("00:00:00".to_owned());
+                assert!(
+                    matches!(
+                        scalar.compare(&other),
+                        Err(ScalarMismatch::UnparseableExpected(_))
+                    ),
+                    "{scalar:?}"
+                );
+                assert!(scalar.normalized().is_err(), "{scalar:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Shared parts of the `toml` and `toml_edit` conversions
+
+use crate::DecodedPath;
+use crate::DecodedScalar;
+use crate::decoded::ScalarType;
+
+/// A [`DecodedScalar`] in the form TOML crates model scalars
+pub(crate) enum Primitive {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Datetime(toml_datetime::Datetime),
+}
+
+impl Primitive {
+    pub(crate) fn new(path: &DecodedPath, scalar: &DecodedScalar) -> Result<Self, crate::Error> {
+        let invalid = |err: String| crate::Error::new(format!("cannot convert {path}: {err}"));
+        let value = scalar.as_str();
+        let primitive = match scalar {
+            DecodedScalar::String(value) => Primitive::String(value.clone()),
+            DecodedScalar::Integer(_) => {
+                let integer = crate::compare::parse_integer(value)
+                    .map_err(invalid)?
+                    .to_i64()
+                    .ok_or_else(|| invalid(format!("integer {value} is out of range")))?;
+                Primitive::Integer(integer)
+            }
+            DecodedScalar::Float(_) => {
+                Primitive::Float(crate::compare::parse_float(value).map_err(invalid)?)
+            }
+            DecodedScalar::Bool(_) => match value {
+                "true" => Primitive::Bool(true),
+                "false" => Primitive::Bool(false),
+                _ => return Err(invalid(format!("invalid bool {value:?}"))),
+            },
+            DecodedScalar::Datetime(_)
+            | DecodedScalar::DatetimeLocal(_)
+            | DecodedScalar::DateLocal(_)
+            | DecodedScalar::TimeLocal(_) => {
+                let normalized = scalar
+                    .normalized()
+                    .map_err(|err| invalid(err.to_string()))?;
+                let datetime = normalized
+                    .as_str()
+                    .parse::<toml_datetime::Datetime>()
+                    .map_err(|err| invalid(format!("invalid datetime {value:?}: {err}")))?;
+                if datetime_type(&datetime) != scalar.scalar_type() {
+                    return Err(invalid(format!(
+                        "invalid {} {value:?}",
+                        scalar.scalar_type().name()
+                    )));
+                }
+                Primitive::Datetime(datetime)
+            }
+        };
+        Ok(primitive)
+    }
+}
+
+pub(crate) fn from_datetime(datetime: &toml_datetime::Datetime) -> DecodedScalar {
+    datetime_type(datetime).scalar(datetime.to_string())
+}
+
+fn datetime_type(datetime: &toml_datetime::Datetime) -> ScalarType {
+    match (datetime.date, datetime.time, datetime.offset) {
+        (Some(_), Some(_), Some(_)) => ScalarType::Datetime,
+        (Some(_), Some(_), None) => ScalarType::DatetimeLocal,
+        (Some(_), None, _) => ScalarType::DateLocal,
+        (None, _, _) => ScalarType::TimeLocal,
+    }
+}
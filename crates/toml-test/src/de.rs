@@ -0,0 +1,515 @@
+use serde::de::IntoDeserializer as _;
+
+use crate::DecodedScalar;
+use crate::DecodedValue;
+use crate::ser::DATETIME_FIELD;
+use crate::ser::DATETIME_STRUCT;
+
+/// Convert a [`DecodedValue`] into any [`serde::Deserialize`] type
+///
+/// Datetimes follow the [`DATETIME_STRUCT`] convention, or are read as strings by types that ask
+/// for one.
+///
+/// # Example
+///
+/// Implementing [`Encoder`][crate::Encoder] for a serde-based TOML crate:
+/// ```rust,ignore
+/// impl toml_test::Encoder for MyEncoder {
+///     fn encode(&self, data: toml_test::DecodedValue) -> Result<String, toml_test::Error> {
+///         let value: my_toml::Table = toml_test::from_decoded(data)?;
+///         my_toml::to_string(&value).map_err(toml_test::Error::new)
+///     }
+///
+///     fn name(&self) -> &str {
+///         "my_toml"
+///     }
+/// }
+/// ```
+pub fn from_decoded<T>(value: DecodedValue) -> Result<T, crate::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+impl<'de> serde::Deserializer<'de> for DecodedValue {
+    type Error = crate::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Scalar(scalar) => scalar.deserialize_any(visitor),
+            Self::Table(table) => {
+                visitor.visit_map(serde::de::value::MapDeserializer::new(table.into_iter()))
+            }
+            Self::Array(array) => {
+                visitor.visit_seq(serde::de::value::SeqDeserializer::new(array.into_iter()))
+            }
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Scalar(scalar) => scalar.deserialize_str(visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Scalar(scalar) if name == DATETIME_STRUCT => {
+                scalar.deserialize_struct(name, fields, visitor)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Scalar(scalar) => scalar.deserialize_enum(name, variants, visitor),
+            Self::Table(table) => {
+                let mut entries = table.into_iter();
+                match (entries.next(), entries.next()) {
+                    (Some((variant, value)), None) => {
+                        visitor.visit_enum(TableEnumAccess { variant, value })
+                    }
+                    _ => Err(crate::Error::new(format!(
+                        "expected a table with a single key for enum `{name}`"
+                    ))),
+                }
+            }
+            Self::Array(_) => Err(crate::Error::new(format!(
+                "expected a string or table for enum `{name}`, found array"
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier
+        ignored_any
+    }
+}
+
+impl<'de> serde::de::IntoDeserializer<'de, crate::Error> for DecodedValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for DecodedScalar {
+    type Error = crate::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::String(v) => visitor.visit_string(v),
+            Self::Datetime(v)
+            | Self::DatetimeLocal(v)
+            | Self::DateLocal(v)
+            | Self::TimeLocal(v) => visitor.visit_map(DatetimeMapAccess { value: Some(v) }),
+            Self::Integer(v) => {
+                let value = crate::compare::parse_integer(&v)
+                    .map_err(crate::Error::new)?
+                    .to_i64()
+                    .ok_or_else(|| crate::Error::new(format!("integer {v} is out of range")))?;
+                visitor.visit_i64(value)
+            }
+            Self::Float(v) => {
+                visitor.visit_f64(crate::compare::parse_float(&v).map_err(crate::Error::new)?)
+            }
+            Self::Bool(v) => match v.as_str() {
+                "true" => visitor.visit_bool(true),
+                "false" => visitor.visit_bool(false),
+                _ => Err(crate::Error::new(format!("invalid bool {v:?}"))),
+            },
+        }
+    }
+
+    /// Datetimes can also be read as strings
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Datetime(v)
+            | Self::DatetimeLocal(v)
+            | Self::DateLocal(v)
+            | Self::TimeLocal(v) => visitor.visit_string(v),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::Datetime(v)
+            | Self::DatetimeLocal(v)
+            | Self::DateLocal(v)
+            | Self::TimeLocal(v)
+                if name == DATETIME_STRUCT =>
+            {
+                visitor.visit_map(DatetimeMapAccess { value: Some(v) })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self {
+            Self::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            _ => Err(crate::Error::new(format!(
+                "expected a string or table for enum `{name}`"
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier
+        ignored_any
+    }
+}
+
+/// Present a datetime as a [`DATETIME_STRUCT`] with a single [`DATETIME_FIELD`]
+struct DatetimeMapAccess {
+    value: Option<String>,
+}
+
+impl<'de> serde::de::MapAccess<'de> for DatetimeMapAccess {
+    type Error = crate::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        if self.value.is_none() {
+            return Ok(None);
+        }
+        seed.deserialize(serde::de::value::BorrowedStrDeserializer::new(
+            DATETIME_FIELD,
+        ))
+        .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let value = self
+            .value
+            .take()
+            .expect("serde calls `next_key_seed` before `next_value_seed`");
+        seed.deserialize(value.into_deserializer())
+    }
+}
+
+/// Present a single-entry table as an enum variant
+struct TableEnumAccess {
+    variant: String,
+    value: DecodedValue,
+}
+
+impl<'de> serde::de::EnumAccess<'de> for TableEnumAccess {
+    type Error = crate::Error;
+    type Variant = DecodedValue;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> serde::de::VariantAccess<'de> for DecodedValue {
+    type Error = crate::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self {
+            Self::Table(table) if table.is_empty() => Ok(()),
+            _ => Err(crate::Error::new(
+                "expected an empty table for unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        serde::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        serde::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Datetime(String);
+
+    impl<'de> serde::Deserialize<'de> for Datetime {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            struct Visitor;
+
+            impl<'de> serde::de::Visitor<'de> for Visitor {
+                type Value = Datetime;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    formatter.write_str("a datetime")
+                }
+
+                fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                where
+                    A: serde::de::MapAccess<'de>,
+                {
+                    let key: String = map
+                        .next_key()?
+                        .ok_or_else(|| serde::de::Error::custom("missing datetime"))?;
+                    assert_eq!(key, DATETIME_FIELD);
+                    Ok(Datetime(map.next_value()?))
+                }
+            }
+
+            deserializer.deserialize_struct(DATETIME_STRUCT, &[DATETIME_FIELD], Visitor)
+        }
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Config {
+        name: String,
+        missing: Option<String>,
+        ports: Vec<u16>,
+        ratio: f32,
+        enabled: bool,
+        owner: Owner,
+        mode: Mode,
+        shape: Shape,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Owner {
+        dob: Datetime,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    enum Mode {
+        Fast,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    enum Shape {
+        Circle { radius: f64 },
+    }
+
+    #[test]
+    fn structures() {
+        let decoded = DecodedValue::from_slice(
+            br#"{
+                "name": {"type": "string", "value": "test"},
+                "ports": [{"type": "integer", "value": "80"}, {"type": "integer", "value": "443"}],
+                "ratio": {"type": "float", "value": "0.5"},
+                "enabled": {"type": "bool", "value": "true"},
+                "owner": {"dob": {"type": "date-local", "value": "1979-05-27"}},
+                "mode": {"type": "string", "value": "Fast"},
+                "shape": {"Circle": {"radius": {"type": "float", "value": "1.0"}}}
+            }"#,
+        )
+        .unwrap();
+        let expected = Config {
+            name: "test".to_owned(),
+            missing: None,
+            ports: vec![80, 443],
+            ratio: 0.5,
+            enabled: true,
+            owner: Owner {
+                dob: Datetime("1979-05-27".to_owned()),
+            },
+            mode: Mode::Fast,
+            shape: Shape::Circle { radius: 1.0 },
+        };
+        assert_eq!(from_decoded::<Config>(decoded).unwrap(), expected);
+    }
+
+    #[test]
+    fn datetimes() {
+        let decoded = crate::decoded!({
+            "a": datetime("1979-05-27T07:32:00Z"),
+            "b": date_local("1979-05-27"),
+        });
+        let value: std::collections::BTreeMap<String, String> =
+            from_decoded(decoded.clone()).unwrap();
+        assert_eq!(value["a"], "1979-05-27T07:32:00Z");
+        let value: std::collections::BTreeMap<String, serde_json::Value> =
+            from_decoded(decoded).unwrap();
+        assert_eq!(
+            value["b"],
+            serde_json::json!({ DATETIME_FIELD: "1979-05-27" })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn toml_datetimes() {
+        let decoded = crate::decoded!({
+            "a": datetime("1979-05-27T07:32:00Z"),
+            "b": date_local("1979-05-27"),
+        });
+        let table = from_decoded::<toml::Table>(decoded).unwrap();
+        assert!(matches!(table["a"], toml::Value::Datetime(_)));
+        assert_eq!(
+            toml::to_string(&table).unwrap(),
+            "a = 1979-05-27T07:32:00Z\nb = 1979-05-27\n"
+        );
+    }
+
+    #[test]
+    fn malformed_scalars() {
+        let integer = DecodedValue::Scalar(DecodedScalar::Integer("12x".to_owned()));
+        assert!(from_decoded::<i64>(integer).is_err());
+        let integer =
+            DecodedValue::Scalar(DecodedScalar::Integer("9223372036854775808".to_owned()));
+        assert!(from_decoded::<i64>(integer).is_err());
+        let integer = DecodedValue::Scalar(DecodedScalar::Integer("300".to_owned()));
+        assert!(from_decoded::<u8>(integer).is_err());
+        let bool = DecodedValue::Scalar(DecodedScalar::Bool("yes".to_owned()));
+        assert!(from_decoded::<bool>(bool).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let decoded = DecodedValue::from_slice(
+            br#"{
+                "a": {"type": "integer", "value": "1"},
+                "b": [{"type": "float", "value": "inf"}],
+                "c": {"d": {"type": "string", "value": "e"}}
+            }"#,
+        )
+        .unwrap();
+        let value: std::collections::BTreeMap<String, serde_json::Value> =
+            from_decoded(decoded.clone()).unwrap();
+        assert_eq!(value["a"], serde_json::json!(1));
+        let actual = crate::to_decoded(&from_decoded::<Config2>(decoded.clone()).unwrap()).unwrap();
+        assert_eq!(actual, decoded);
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Config2 {
+        a: i64,
+        b: Vec<f64>,
+        c: std::collections::BTreeMap<String, String>,
+    }
+}
@@ -1,15 +1,35 @@
 use std::io::Read;
 use std::io::Write;
 
+use crate::JsonFormat;
+use crate::compare::ComparePolicy;
+use crate::compare::ScalarKey;
+use crate::compare::ScalarMismatch;
+use crate::compare::normalize_scalar;
+use crate::compare::scalar_cmp;
+use crate::compare::scalar_eq;
+
 /// Logical representation of any TOML value
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(untagged)]
 pub enum DecodedValue {
     Scalar(DecodedScalar),
-    Table(std::collections::HashMap<String, DecodedValue>),
+    Table(#[serde(serialize_with = "serialize_table")] DecodedTable),
     Array(Vec<DecodedValue>),
 }
 
+/// Table of [`DecodedValue`]s
+///
+/// With the `preserve_order` feature, keys are kept in the order they were inserted / read.
+#[cfg(not(feature = "preserve_order"))]
+pub type DecodedTable = std::collections::HashMap<String, DecodedValue>;
+
+/// Table of [`DecodedValue`]s
+///
+/// With the `preserve_order` feature, keys are kept in the order they were inserted / read.
+#[cfg(feature = "preserve_order")]
+pub type DecodedTable = indexmap::IndexMap<String, DecodedValue>;
+
 impl DecodedValue {
     pub fn from_slice(v: &[u8]) -> Result<Self, crate::Error> {
         serde_json::from_slice(v).map_err(|e| {
@@ -18,19 +38,56 @@ impl DecodedValue {
                 e,
                 String::from_utf8_lossy(v)
             ))
+            .with_kind(crate::ErrorKind::Protocol)
+            .with_source(e)
         })
     }
 
+    /// Load a specific revision of the toml-test tagged-JSON format, upgrading
+    /// [`JsonFormat::Legacy`] to the current model
+    pub fn from_slice_with_format(v: &[u8], format: JsonFormat) -> Result<Self, crate::Error> {
+        match format {
+            JsonFormat::Current => Self::from_slice(v),
+            JsonFormat::Legacy => crate::legacy::from_slice(v),
+        }
+    }
+
+    /// Load the toml-test tagged-JSON format, rejecting anything [`DecodedValue::from_slice`]
+    /// would let slip through
+    ///
+    /// This reports every unknown `type` tag, duplicate key, and lexically malformed scalar
+    /// `value` (e.g. a `bool` that is not `true` / `false`) with its path and line / column.
+    pub fn from_slice_strict(v: &[u8]) -> Result<Self, crate::Error> {
+        crate::strict::from_slice(v)
+    }
+
+    /// Render as indented JSON
+    ///
+    /// Keys are sorted (in insertion order with the `preserve_order` feature), so the output is
+    /// the same on every run.
     pub fn to_string_pretty(&self) -> Result<String, crate::Error> {
         serde_json::to_string_pretty(self).map_err(crate::Error::new)
     }
 
+    /// Render as indented JSON in a specific revision of the toml-test tagged-JSON format
+    pub fn to_string_pretty_with_format(&self, format: JsonFormat) -> Result<String, crate::Error> {
+        match format {
+            JsonFormat::Current => self.to_string_pretty(),
+            JsonFormat::Legacy => crate::legacy::to_string_pretty(self),
+        }
+    }
+
+    /// Render as single-line JSON, with keys ordered like [`DecodedValue::to_string_pretty`]
+    pub fn to_string_compact(&self) -> Result<String, crate::Error> {
+        serde_json::to_string(self).map_err(crate::Error::new)
+    }
+
     /// See [`Command`][crate::Command]
     pub fn from_stdin() -> Result<Self, crate::Error> {
         let mut buf = Vec::new();
         std::io::stdin()
             .read_to_end(&mut buf)
-            .map_err(crate::Error::new)?;
+            .map_err(|err| crate::Error::io("failed to read stdin", err))?;
         Self::from_slice(&buf)
     }
 
@@ -39,7 +96,688 @@ impl DecodedValue {
         let s = self.to_string_pretty()?;
         std::io::stdout()
             .write_all(s.as_bytes())
-            .map_err(crate::Error::new)
+            .map_err(|err| crate::Error::io("failed to write stdout", err))
+    }
+
+    /// Look up the value at `path`
+    pub fn get_path(&self, path: &DecodedPath) -> Option<&DecodedValue> {
+        let mut current = self;
+        for segment in path.segments() {
+            current = match (current, segment) {
+                (DecodedValue::Table(t), PathSegment::Key(k)) => t.get(k)?,
+                (DecodedValue::Array(a), PathSegment::Index(i)) => a.get(*i)?,
+                (_, _) => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Look up the value at `path`, like `servers.alpha.ip` or `"quoted key".list[3]`
+    ///
+    /// See [`DecodedPath`] for the syntax.
+    pub fn query(&self, path: &str) -> Result<Option<&DecodedValue>, crate::Error> {
+        let path = path.parse::<DecodedPath>()?;
+        Ok(self.get_path(&path))
+    }
+
+    /// Iterate over every [`DecodedScalar`] with its full path
+    ///
+    /// Tables are visited in their iteration order (see [`DecodedTable`]) and empty tables and
+    /// arrays are skipped.
+    pub fn leaves(&self) -> Leaves<'_> {
+        Leaves {
+            stack: vec![(DecodedPath::root(), self)],
+        }
+    }
+
+    /// Rewrite every [`DecodedScalar`] into its canonical spelling
+    ///
+    /// See [`DecodedScalar::normalized`]; errors report the path of the first malformed scalar.
+    pub fn normalized(&self) -> Result<DecodedValue, crate::Error> {
+        normalize_value(&mut DecodedPath::root(), self)
+    }
+
+    /// Find every [`Difference`] between `self` (expected) and `actual`
+    ///
+    /// An empty result means the two values are equal.
+    pub fn diff(&self, actual: &DecodedValue) -> Vec<Difference> {
+        self.diff_with(actual, &ComparePolicy::new())
+    }
+
+    /// Find every [`Difference`] between `self` (expected) and `actual` according to `policy`
+    pub fn diff_with(&self, actual: &DecodedValue, policy: &ComparePolicy) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        let mut path = DecodedPath::root();
+        diff_value(&mut path, self, actual, policy, &mut differences);
+        differences
+    }
+
+    /// Find every table whose keys are in a different order in `actual` than in `self` (expected)
+    ///
+    /// Only keys present in both tables are considered; use [`DecodedValue::diff`] for the
+    /// contents.
+    #[cfg(feature = "preserve_order")]
+    pub fn diff_key_order(&self, actual: &DecodedValue) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        let mut path = DecodedPath::root();
+        diff_key_order(&mut path, self, actual, &mut differences);
+        differences
+    }
+}
+
+/// Consistent with [`PartialEq`], ignoring the order of table keys
+impl std::hash::Hash for DecodedValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            DecodedValue::Scalar(scalar) => {
+                state.write_u8(0);
+                scalar.hash(state);
+            }
+            DecodedValue::Table(table) => {
+                state.write_u8(1);
+                sorted_entries(table).hash(state);
+            }
+            DecodedValue::Array(items) => {
+                state.write_u8(2);
+                items.hash(state);
+            }
+        }
+    }
+}
+
+impl PartialOrd for DecodedValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Consistent with [`PartialEq`]
+///
+/// Scalars sort before tables which sort before arrays.  Tables are compared as their entries
+/// sorted by key, ignoring the order of table keys.
+impl Ord for DecodedValue {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (DecodedValue::Scalar(s), DecodedValue::Scalar(o)) => s.cmp(o),
+            (DecodedValue::Table(s), DecodedValue::Table(o)) => {
+                sorted_entries(s).cmp(&sorted_entries(o))
+            }
+            (DecodedValue::Array(s), DecodedValue::Array(o)) => s.cmp(o),
+            (s, o) => rank(s).cmp(&rank(o)),
+        }
+    }
+}
+
+fn rank(value: &DecodedValue) -> u8 {
+    match value {
+        DecodedValue::Scalar(_) => 0,
+        DecodedValue::Table(_) => 1,
+        DecodedValue::Array(_) => 2,
+    }
+}
+
+/// Write keys in a deterministic order, see [`DecodedValue::to_string_pretty`]
+fn serialize_table<S>(table: &DecodedTable, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_map(ordered_entries(table))
+}
+
+/// Entries in the order they are serialized, see [`DecodedValue::to_string_pretty`]
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn ordered_entries(table: &DecodedTable) -> Vec<(&String, &DecodedValue)> {
+    sorted_entries(table)
+}
+
+/// Entries in the order they are serialized, see [`DecodedValue::to_string_pretty`]
+#[cfg(feature = "preserve_order")]
+pub(crate) fn ordered_entries(table: &DecodedTable) -> Vec<(&String, &DecodedValue)> {
+    table.iter().collect()
+}
+
+fn sorted_entries(table: &DecodedTable) -> Vec<(&String, &DecodedValue)> {
+    let mut entries = table.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(k, _)| *k);
+    entries
+}
+
+fn normalize_value(
+    path: &mut DecodedPath,
+    value: &DecodedValue,
+) -> Result<DecodedValue, crate::Error> {
+    match value {
+        DecodedValue::Scalar(scalar) => {
+            let normalized = normalize_scalar(scalar.scalar_type(), scalar.as_str())
+                .map_err(|err| crate::Error::new(format!("cannot normalize {path}: {err}")))?;
+            Ok(DecodedValue::Scalar(
+                scalar.scalar_type().scalar(normalized),
+            ))
+        }
+        DecodedValue::Table(table) => {
+            let mut normalized = DecodedTable::default();
+            for (key, value) in table {
+                path.push(PathSegment::Key(key.clone()));
+                normalized.insert(key.clone(), normalize_value(path, value)?);
+                path.pop();
+            }
+            Ok(DecodedValue::Table(normalized))
+        }
+        DecodedValue::Array(items) => {
+            let mut normalized = Vec::with_capacity(items.len());
+            for (i, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                normalized.push(normalize_value(path, item)?);
+                path.pop();
+            }
+            Ok(DecodedValue::Array(normalized))
+        }
+    }
+}
+
+fn diff_value(
+    path: &mut DecodedPath,
+    expected: &DecodedValue,
+    actual: &DecodedValue,
+    policy: &ComparePolicy,
+    differences: &mut Vec<Difference>,
+) {
+    match (expected, actual) {
+        (DecodedValue::Scalar(e), DecodedValue::Scalar(a)) => {
+            if e.type_name() != a.type_name() {
+                differences.push(Difference {
+                    path: path.clone(),
+                    kind: DifferenceKind::TypeMismatch {
+                        expected: expected.clone(),
+                        actual: actual.clone(),
+                    },
+                });
+            } else if let Err(reason) = e.compare_with(a, policy) {
+                differences.push(Difference {
+                    path: path.clone(),
+                    kind: DifferenceKind::ValueMismatch {
+                        expected: e.clone(),
+                        actual: a.clone(),
+                        reason,
+                    },
+                });
+            }
+        }
+        (DecodedValue::Table(e), DecodedValue::Table(a)) => {
+            let mut keys = e.keys().chain(a.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.segments.push(PathSegment::Key(key.clone()));
+                match (e.get(key), a.get(key)) {
+                    (Some(e), Some(a)) => diff_value(path, e, a, policy, differences),
+                    (Some(e), None) => differences.push(Difference {
+                        path: path.clone(),
+                        kind: DifferenceKind::Missing {
+                            expected: e.clone(),
+                        },
+                    }),
+                    (None, Some(a)) => differences.push(Difference {
+                        path: path.clone(),
+                        kind: DifferenceKind::Extra { actual: a.clone() },
+                    }),
+                    (None, None) => unreachable!("key came from one of the tables"),
+                }
+                path.segments.pop();
+            }
+        }
+        (DecodedValue::Array(e), DecodedValue::Array(a)) => {
+            for index in 0..e.len().max(a.len()) {
+                path.segments.push(PathSegment::Index(index));
+                match (e.get(index), a.get(index)) {
+                    (Some(e), Some(a)) => diff_value(path, e, a, policy, differences),
+                    (Some(e), None) => differences.push(Difference {
+                        path: path.clone(),
+                        kind: DifferenceKind::Missing {
+                            expected: e.clone(),
+                        },
+                    }),
+                    (None, Some(a)) => differences.push(Difference {
+                        path: path.clone(),
+                        kind: DifferenceKind::Extra { actual: a.clone() },
+                    }),
+                    (None, None) => unreachable!("index is within one of the arrays"),
+                }
+                path.segments.pop();
+            }
+        }
+        (_, _) => differences.push(Difference {
+            path: path.clone(),
+            kind: DifferenceKind::TypeMismatch {
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+        }),
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+fn diff_key_order(
+    path: &mut DecodedPath,
+    expected: &DecodedValue,
+    actual: &DecodedValue,
+    differences: &mut Vec<Difference>,
+) {
+    match (expected, actual) {
+        (DecodedValue::Table(e), DecodedValue::Table(a)) => {
+            let expected_order = e
+                .keys()
+                .filter(|k| a.contains_key(*k))
+                .cloned()
+                .collect::<Vec<_>>();
+            let actual_order = a
+                .keys()
+                .filter(|k| e.contains_key(*k))
+                .cloned()
+                .collect::<Vec<_>>();
+            if expected_order != actual_order {
+                differences.push(Difference {
+                    path: path.clone(),
+                    kind: DifferenceKind::KeyOrder {
+                        expected: expected_order,
+                        actual: actual_order,
+                    },
+                });
+            }
+            for (key, e) in e {
+                if let Some(a) = a.get(key) {
+                    path.segments.push(PathSegment::Key(key.clone()));
+                    diff_key_order(path, e, a, differences);
+                    path.segments.pop();
+                }
+            }
+        }
+        (DecodedValue::Array(e), DecodedValue::Array(a)) => {
+            for (index, (e, a)) in e.iter().zip(a).enumerate() {
+                path.segments.push(PathSegment::Index(index));
+                diff_key_order(path, e, a, differences);
+                path.segments.pop();
+            }
+        }
+        (_, _) => {}
+    }
+}
+
+/// Iterator over the scalars in a [`DecodedValue`], see [`DecodedValue::leaves`]
+pub struct Leaves<'v> {
+    stack: Vec<(DecodedPath, &'v DecodedValue)>,
+}
+
+impl<'v> Iterator for Leaves<'v> {
+    type Item = (DecodedPath, &'v DecodedScalar);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, value)) = self.stack.pop() {
+            match value {
+                DecodedValue::Scalar(s) => return Some((path, s)),
+                DecodedValue::Table(t) => {
+                    let children = t.iter().map(|(k, v)| {
+                        let mut path = path.clone();
+                        path.push(PathSegment::Key(k.clone()));
+                        (path, v)
+                    });
+                    let start = self.stack.len();
+                    self.stack.extend(children);
+                    self.stack[start..].reverse();
+                }
+                DecodedValue::Array(a) => {
+                    let children = a.iter().enumerate().map(|(i, v)| {
+                        let mut path = path.clone();
+                        path.push(PathSegment::Index(i));
+                        (path, v)
+                    });
+                    let start = self.stack.len();
+                    self.stack.extend(children);
+                    self.stack[start..].reverse();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Location of a value within a [`DecodedValue`], like `a.b[3].c`
+///
+/// When parsed, keys follow TOML's dotted key syntax (bare, `"basic"` or `'literal'` keys
+/// separated by `.`) and array indices are written as `[3]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DecodedPath {
+    segments: Vec<PathSegment>,
+}
+
+impl DecodedPath {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+}
+
+impl std::str::FromStr for DecodedPath {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = PathParser {
+            input: s,
+            chars: s.char_indices().peekable(),
+        };
+        parser.parse()
+    }
+}
+
+struct PathParser<'s> {
+    input: &'s str,
+    chars: std::iter::Peekable<std::str::CharIndices<'s>>,
+}
+
+impl PathParser<'_> {
+    fn parse(&mut self) -> Result<DecodedPath, crate::Error> {
+        let mut path = DecodedPath::root();
+        self.skip_whitespace();
+        match self.chars.peek() {
+            None => return Ok(path),
+            Some((_, '[')) => {}
+            Some(_) => path.push(PathSegment::Key(self.key()?)),
+        }
+        loop {
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, '.')) => {
+                    self.skip_whitespace();
+                    path.push(PathSegment::Key(self.key()?));
+                }
+                Some((_, '[')) => path.push(PathSegment::Index(self.index()?)),
+                Some((i, c)) => return Err(self.error(i, &format!("unexpected `{c}`"))),
+                None => return Ok(path),
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, crate::Error> {
+        match self.chars.peek().copied() {
+            Some((_, '"')) => {
+                self.chars.next();
+                self.basic_string()
+            }
+            Some((_, '\'')) => {
+                self.chars.next();
+                self.literal_string()
+            }
+            Some((_, c)) if is_bare_key_char(c) => {
+                let mut key = String::new();
+                while let Some((_, c)) = self.chars.peek().copied() {
+                    if !is_bare_key_char(c) {
+                        break;
+                    }
+                    key.push(c);
+                    self.chars.next();
+                }
+                Ok(key)
+            }
+            Some((i, c)) => Err(self.error(i, &format!("invalid key character `{c}`"))),
+            None => Err(self.error(self.input.len(), "expected key")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, crate::Error> {
+        let mut key = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(key),
+                Some((i, '\\')) => {
+                    let c = match self.chars.next() {
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 'e')) => '\u{1b}',
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, 'x')) => self.hex(i, 2)?,
+                        Some((_, 'u')) => self.hex(i, 4)?,
+                        Some((_, 'U')) => self.hex(i, 8)?,
+                        _ => return Err(self.error(i, "invalid escape")),
+                    };
+                    key.push(c);
+                }
+                Some((_, c)) => key.push(c),
+                None => return Err(self.error(self.input.len(), "unterminated string")),
+            }
+        }
+    }
+
+    fn hex(&mut self, start: usize, digits: usize) -> Result<char, crate::Error> {
+        let mut code = 0;
+        for _ in 0..digits {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| self.error(start, "invalid escape"))?;
+            code = code * 16 + digit;
+        }
+        char::from_u32(code).ok_or_else(|| self.error(start, "invalid escape"))
+    }
+
+    fn literal_string(&mut self) -> Result<String, crate::Error> {
+        let mut key = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '\'')) => return Ok(key),
+                Some((_, c)) => key.push(c),
+                None => return Err(self.error(self.input.len(), "unterminated string")),
+            }
+        }
+    }
+
+    fn index(&mut self) -> Result<usize, crate::Error> {
+        let start = self.offset();
+        self.skip_whitespace();
+        let mut digits = String::new();
+        while let Some((_, c)) = self.chars.peek().copied() {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            digits.push(c);
+            self.chars.next();
+        }
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, ']')) => digits
+                .parse()
+                .map_err(|_| self.error(start, "expected array index")),
+            _ => Err(self.error(start, "expected array index")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| *c == ' ' || *c == '\t')
+            .is_some()
+        {}
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or(self.input.len())
+    }
+
+    fn error(&self, offset: usize, message: &str) -> crate::Error {
+        crate::Error::new(format!(
+            "invalid path `{}` at offset {offset}: {message}",
+            self.input
+        ))
+    }
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+impl std::fmt::Display for DecodedPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return f.write_str("<root>");
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) => {
+                    if i != 0 {
+                        f.write_str(".")?;
+                    }
+                    write_key(f, key)?;
+                }
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<PathSegment> for DecodedPath {
+    fn from_iter<I: IntoIterator<Item = PathSegment>>(iter: I) -> Self {
+        Self {
+            segments: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// A part of [`DecodedPath`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+pub(crate) fn write_key(f: &mut impl std::fmt::Write, key: &str) -> std::fmt::Result {
+    let is_bare = !key.is_empty() && key.chars().all(is_bare_key_char);
+    if is_bare {
+        return f.write_str(key);
+    }
+    write_basic_string(f, key)
+}
+
+/// Write `s` as a TOML basic string, valid for all spec versions
+pub(crate) fn write_basic_string(f: &mut impl std::fmt::Write, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str(r#"\""#)?,
+            '\\' => f.write_str(r"\\")?,
+            '\n' => f.write_str(r"\n")?,
+            '\t' => f.write_str(r"\t")?,
+            '\r' => f.write_str(r"\r")?,
+            c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// A mismatch found by [`DecodedValue::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub path: DecodedPath,
+    pub kind: DifferenceKind,
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.kind {
+            DifferenceKind::Missing { expected } => {
+                write!(f, "missing, expected {}", Summary(expected))
+            }
+            DifferenceKind::Extra { actual } => write!(f, "unexpected {}", Summary(actual)),
+            DifferenceKind::TypeMismatch { expected, actual } => write!(
+                f,
+                "expected {}, found {}",
+                Summary(expected),
+                Summary(actual)
+            ),
+            DifferenceKind::ValueMismatch {
+                expected,
+                actual,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "expected {} {:?}, found {:?}",
+                    expected.type_name(),
+                    expected.as_str(),
+                    actual.as_str()
+                )?;
+                if *reason != ScalarMismatch::DifferentValue {
+                    write!(f, " ({reason})")?;
+                }
+                Ok(())
+            }
+            DifferenceKind::KeyOrder { expected, actual } => {
+                write!(f, "expected keys in order {expected:?}, found {actual:?}")
+            }
+        }
+    }
+}
+
+/// What kind of mismatch a [`Difference`] is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DifferenceKind {
+    /// Present in the expected value but not in the actual value
+    Missing { expected: DecodedValue },
+    /// Present in the actual value but not in the expected value
+    Extra { actual: DecodedValue },
+    /// Values are of different types, like `integer` vs `float` or `table` vs `array`
+    TypeMismatch {
+        expected: DecodedValue,
+        actual: DecodedValue,
+    },
+    /// Scalars of the same type with different values
+    ValueMismatch {
+        expected: DecodedScalar,
+        actual: DecodedScalar,
+        reason: ScalarMismatch,
+    },
+    /// Tables with the same keys in a different order
+    ///
+    /// Only reported when checking key order with the `preserve_order` feature
+    KeyOrder {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+}
+
+struct Summary<'v>(&'v DecodedValue);
+
+impl std::fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            DecodedValue::Scalar(s) => write!(f, "{} {:?}", s.type_name(), s.as_str()),
+            DecodedValue::Table(t) => write!(f, "table with {} keys", t.len()),
+            DecodedValue::Array(a) => write!(f, "array with {} elements", a.len()),
+        }
     }
 }
 
@@ -59,6 +797,69 @@ pub enum DecodedScalar {
 }
 
 impl DecodedScalar {
+    /// A string, like `TOML Example`
+    pub fn string(value: impl Into<String>) -> Self {
+        DecodedScalar::String(value.into())
+    }
+
+    /// An integer, like `8000`
+    pub fn integer(value: i64) -> Self {
+        DecodedScalar::from(value)
+    }
+
+    /// A float, like `0.5`, with `nan` and `inf` written the way toml-test expects
+    pub fn float(value: f64) -> Self {
+        DecodedScalar::from(value)
+    }
+
+    /// A boolean, `true` or `false`
+    pub fn bool(value: bool) -> Self {
+        DecodedScalar::from(value)
+    }
+
+    /// An offset datetime, like `1979-05-27T07:32:00Z`
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not a valid datetime
+    pub fn datetime(value: &str) -> Self {
+        Self::checked(ScalarType::Datetime, value)
+    }
+
+    /// A local datetime, like `1979-05-27T07:32:00`
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not a valid local datetime
+    pub fn datetime_local(value: &str) -> Self {
+        Self::checked(ScalarType::DatetimeLocal, value)
+    }
+
+    /// A local date, like `1979-05-27`
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not a valid local date
+    pub fn date_local(value: &str) -> Self {
+        Self::checked(ScalarType::DateLocal, value)
+    }
+
+    /// A local time, like `07:32:00`
+    ///
+    /// # Panics
+    ///
+    /// If `value` is not a valid local time
+    pub fn time_local(value: &str) -> Self {
+        Self::checked(ScalarType::TimeLocal, value)
+    }
+
+    fn checked(ty: ScalarType, value: &str) -> Self {
+        if let Err(err) = crate::compare::validate_scalar(ty, value) {
+            panic!("{err}");
+        }
+        ty.scalar(value.to_owned())
+    }
+
     pub fn as_str(&self) -> &str {
         match self {
             DecodedScalar::String(v)
@@ -71,6 +872,121 @@ impl DecodedScalar {
             | DecodedScalar::TimeLocal(v) => v.as_str(),
         }
     }
+
+    /// Compare `self` (expected) with `actual`, explaining any mismatch
+    ///
+    /// Malformed values are reported as [`ScalarMismatch::UnparseableExpected`] or
+    /// [`ScalarMismatch::UnparseableActual`] rather than panicking.
+    pub fn compare(&self, actual: &DecodedScalar) -> Result<(), ScalarMismatch> {
+        self.compare_with(actual, &ComparePolicy::new())
+    }
+
+    /// Compare `self` (expected) with `actual` according to `policy`
+    pub fn compare_with(
+        &self,
+        actual: &DecodedScalar,
+        policy: &ComparePolicy,
+    ) -> Result<(), ScalarMismatch> {
+        let ty = self.scalar_type();
+        let actual_ty = actual.scalar_type();
+        if ty != actual_ty {
+            return Err(ScalarMismatch::DifferentType {
+                expected: ty.name(),
+                actual: actual_ty.name(),
+            });
+        }
+        scalar_cmp(ty, self.as_str(), actual.as_str(), policy)
+    }
+
+    /// Rewrite the value into the one canonical spelling for its type
+    ///
+    /// This makes diffs, snapshots, and hashes independent of how a decoder spelled a value:
+    /// - Integers are plain decimal, e.g. `+0_1` becomes `1`
+    /// - Floats are the shortest text that round-trips, with `inf`, `-inf`, `nan`, and `-nan`
+    ///   for the special values
+    /// - Datetimes use `T` and `Z`, always have seconds, and have no trailing zeros in their
+    ///   fractional seconds
+    ///
+    /// Strings and bools are unchanged. Malformed values are an error.
+    pub fn normalized(&self) -> Result<DecodedScalar, crate::Error> {
+        let ty = self.scalar_type();
+        normalize_scalar(ty, self.as_str())
+            .map(|value| ty.scalar(value))
+            .map_err(crate::Error::new)
+    }
+
+    pub(crate) fn scalar_type(&self) -> ScalarType {
+        match self {
+            DecodedScalar::String(_) => ScalarType::String,
+            DecodedScalar::Integer(_) => ScalarType::Integer,
+            DecodedScalar::Float(_) => ScalarType::Float,
+            DecodedScalar::Bool(_) => ScalarType::Bool,
+            DecodedScalar::Datetime(_) => ScalarType::Datetime,
+            DecodedScalar::DatetimeLocal(_) => ScalarType::DatetimeLocal,
+            DecodedScalar::DateLocal(_) => ScalarType::DateLocal,
+            DecodedScalar::TimeLocal(_) => ScalarType::TimeLocal,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.scalar_type().name()
+    }
+}
+
+/// The `type` tag of a [`DecodedScalar`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum ScalarType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Datetime,
+    DatetimeLocal,
+    DateLocal,
+    TimeLocal,
+}
+
+impl ScalarType {
+    pub(crate) const ALL: [ScalarType; 8] = [
+        ScalarType::String,
+        ScalarType::Integer,
+        ScalarType::Float,
+        ScalarType::Bool,
+        ScalarType::Datetime,
+        ScalarType::DatetimeLocal,
+        ScalarType::DateLocal,
+        ScalarType::TimeLocal,
+    ];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub(crate) fn scalar(self, value: String) -> DecodedScalar {
+        match self {
+            ScalarType::String => DecodedScalar::String(value),
+            ScalarType::Integer => DecodedScalar::Integer(value),
+            ScalarType::Float => DecodedScalar::Float(value),
+            ScalarType::Bool => DecodedScalar::Bool(value),
+            ScalarType::Datetime => DecodedScalar::Datetime(value),
+            ScalarType::DatetimeLocal => DecodedScalar::DatetimeLocal(value),
+            ScalarType::DateLocal => DecodedScalar::DateLocal(value),
+            ScalarType::TimeLocal => DecodedScalar::TimeLocal(value),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ScalarType::String => "string",
+            ScalarType::Integer => "integer",
+            ScalarType::Float => "float",
+            ScalarType::Bool => "bool",
+            ScalarType::Datetime => "datetime",
+            ScalarType::DatetimeLocal => "datetime-local",
+            ScalarType::DateLocal => "date-local",
+            ScalarType::TimeLocal => "time-local",
+        }
+    }
 }
 
 impl<'a> From<&'a str> for DecodedScalar {
@@ -122,82 +1038,42 @@ impl From<bool> for DecodedScalar {
 
 impl PartialEq for DecodedScalar {
     fn eq(&self, other: &Self) -> bool {
-        #[allow(clippy::if_same_then_else)]
-        match (self, other) {
-            (DecodedScalar::String(s), DecodedScalar::String(o)) => s == o,
-            (DecodedScalar::Integer(s), DecodedScalar::Integer(o)) => s == o,
-            (DecodedScalar::Float(s), DecodedScalar::Float(o)) => {
-                if s == "inf" && o == "+inf" {
-                    true
-                } else if s == "+inf" && o == "inf" {
-                    true
-                } else if s == "nan" && o == "nan" {
-                    true
-                } else {
-                    let s = s.parse::<f64>().unwrap();
-                    let o = o.parse::<f64>().unwrap();
-                    s == o
-                }
-            }
-            (DecodedScalar::Bool(s), DecodedScalar::Bool(o)) => s == o,
-            (DecodedScalar::Datetime(s), DecodedScalar::Datetime(o)) => {
-                parse_date_time(s) == parse_date_time(o)
-            }
-            (DecodedScalar::DatetimeLocal(s), DecodedScalar::DatetimeLocal(o)) => {
-                parse_date_time_local(s) == parse_date_time_local(o)
-            }
-            (DecodedScalar::DateLocal(s), DecodedScalar::DateLocal(o)) => {
-                parse_date_local(s) == parse_date_local(o)
-            }
-            (DecodedScalar::TimeLocal(s), DecodedScalar::TimeLocal(o)) => {
-                parse_time_local(s) == parse_time_local(o)
-            }
-            (_, _) => false,
-        }
-    }
-}
-
-fn parse_date_time(s: &str) -> chrono::DateTime<chrono::FixedOffset> {
-    match normalize_datetime(s).parse() {
-        Ok(d) => d,
-        Err(err) => panic!("Failed to parse {s:?}: {err}"),
+        let ty = self.scalar_type();
+        ty == other.scalar_type() && scalar_eq(ty, self.as_str(), other.as_str())
     }
 }
 
-fn parse_date_time_local(s: &str) -> chrono::NaiveDateTime {
-    match normalize_datetime(s).parse() {
-        Ok(d) => d,
-        Err(err) => panic!("Failed to parse {s:?}: {err}"),
-    }
-}
+impl Eq for DecodedScalar {}
 
-fn parse_date_local(s: &str) -> chrono::NaiveDate {
-    match s.parse() {
-        Ok(d) => d,
-        Err(err) => panic!("Failed to parse {s:?}: {err}"),
+/// Consistent with [`PartialEq`], e.g. `inf` and `+inf` hash the same
+impl std::hash::Hash for DecodedScalar {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let ty = self.scalar_type();
+        ty.hash(state);
+        ScalarKey::new(ty, self.as_str()).hash(state);
     }
 }
 
-fn parse_time_local(s: &str) -> chrono::NaiveTime {
-    match s.parse() {
-        Ok(d) => d,
-        Err(err) => panic!("Failed to parse {s:?}: {err}"),
+impl PartialOrd for DecodedScalar {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-fn normalize_datetime(s: &str) -> String {
-    s.chars()
-        .map(|c| match c {
-            ' ' => 'T',
-            't' => 'T',
-            'z' => 'Z',
-            _ => c,
+/// Consistent with [`PartialEq`]
+///
+/// Scalars are ordered by type, in the order of the variants, and then by value, e.g.
+/// datetimes by instant and floats with NaN last.  Malformed values sort after well-formed
+/// ones of the same type, by their text.
+impl Ord for DecodedScalar {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let (ty, other_ty) = (self.scalar_type(), other.scalar_type());
+        ty.cmp(&other_ty).then_with(|| {
+            ScalarKey::new(ty, self.as_str()).cmp(&ScalarKey::new(other_ty, other.as_str()))
         })
-        .collect()
+    }
 }
 
-impl Eq for DecodedScalar {}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -393,4 +1269,537 @@ mod test {
             DecodedScalar::from("17:45:00")
         );
     }
+
+    fn table(entries: impl IntoIterator<Item = (&'static str, DecodedValue)>) -> DecodedValue {
+        DecodedValue::Table(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        )
+    }
+
+    fn scalar(s: DecodedScalar) -> DecodedValue {
+        DecodedValue::Scalar(s)
+    }
+
+    #[test]
+    fn compare_malformed() {
+        let expected = DecodedScalar::Datetime("1987-07-05T17:45:00Z".to_owned());
+        let malformed = DecodedScalar::Datetime("1987-07-05 nope".to_owned());
+        assert!(matches!(
+            expected.compare(&malformed),
+            Err(ScalarMismatch::UnparseableActual(_))
+        ));
+        assert!(matches!(
+            malformed.compare(&expected),
+            Err(ScalarMismatch::UnparseableExpected(_))
+        ));
+        assert_ne!(expected, malformed);
+        assert_eq!(malformed, malformed);
+
+        let expected = DecodedScalar::from(1.5);
+        let malformed = DecodedScalar::Float("1.5.0".to_owned());
+        assert!(matches!(
+            expected.compare(&malformed),
+            Err(ScalarMismatch::UnparseableActual(_))
+        ));
+
+        let expected = DecodedScalar::from(true);
+        let malformed = DecodedScalar::Bool("yes".to_owned());
+        assert!(matches!(
+            expected.compare(&malformed),
+            Err(ScalarMismatch::UnparseableActual(_))
+        ));
+
+        for ty in [
+            DecodedScalar::DatetimeLocal,
+            DecodedScalar::DateLocal,
+            DecodedScalar::TimeLocal,
+        ] {
+            assert!(matches!(
+                ty("".to_owned()).compare(&ty("x".to_owned())),
+                Err(ScalarMismatch::UnparseableExpected(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn compare_reasons() {
+        assert_eq!(
+            DecodedScalar::from(1).compare(&DecodedScalar::from(1.0)),
+            Err(ScalarMismatch::DifferentType {
+                expected: "integer",
+                actual: "float"
+            })
+        );
+        assert_eq!(
+            DecodedScalar::from(1).compare(&DecodedScalar::from(2)),
+            Err(ScalarMismatch::DifferentValue)
+        );
+        assert_eq!(
+            DecodedScalar::Datetime("1987-07-05T17:45:00Z".to_owned()).compare(
+                &DecodedScalar::Datetime("1987-07-05T17:45:00+01:00".to_owned())
+            ),
+            Err(ScalarMismatch::DifferentInstant)
+        );
+        assert_eq!(
+            DecodedScalar::Float("nan".to_owned())
+                .compare(&DecodedScalar::Float("-nan".to_owned())),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn diff_malformed() {
+        let expected = table([("a", scalar(DecodedScalar::TimeLocal("17:45:00".to_owned())))]);
+        let actual = table([("a", scalar(DecodedScalar::TimeLocal("5pm".to_owned())))]);
+        let differences = expected
+            .diff(&actual)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                r#"a: expected time-local "17:45:00", found "5pm" (unparseable actual value: invalid time-local "5pm": input contains invalid characters)"#
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_equal() {
+        let value = table([("a", scalar(DecodedScalar::from(1)))]);
+        assert_eq!(value.diff(&value), vec![]);
+    }
+
+    #[test]
+    fn diff_paths() {
+        let expected = table([(
+            "a",
+            table([(
+                "b",
+                DecodedValue::Array(vec![
+                    scalar(DecodedScalar::from(1)),
+                    table([("c", scalar(DecodedScalar::from("x")))]),
+                ]),
+            )]),
+        )]);
+        let actual = table([(
+            "a",
+            table([(
+                "b",
+                DecodedValue::Array(vec![
+                    scalar(DecodedScalar::from(1.0)),
+                    table([("c", scalar(DecodedScalar::from("y")))]),
+                    scalar(DecodedScalar::from(true)),
+                ]),
+            )]),
+        )]);
+        let differences = expected
+            .diff(&actual)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                r#"a.b[0]: expected integer "1", found float "1.0""#.to_owned(),
+                r#"a.b[1].c: expected string "x", found "y""#.to_owned(),
+                r#"a.b[2]: unexpected bool "true""#.to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_missing_and_extra_keys() {
+        let expected = table([
+            ("same", scalar(DecodedScalar::from(1))),
+            ("key with space", scalar(DecodedScalar::from(2))),
+        ]);
+        let actual = table([
+            ("same", scalar(DecodedScalar::from(1))),
+            ("other", DecodedValue::Array(vec![])),
+        ]);
+        let differences = expected
+            .diff(&actual)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                r#""key with space": missing, expected integer "2""#.to_owned(),
+                "other: unexpected array with 0 elements".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "preserve_order")]
+    fn diff_key_order() {
+        let expected = DecodedValue::from_slice(
+            br#"{
+                "b": {"type": "integer", "value": "1"},
+                "a": {"z": {"type": "bool", "value": "true"}, "y": []}
+            }"#,
+        )
+        .unwrap();
+        let actual = DecodedValue::from_slice(
+            br#"{
+                "b": {"type": "integer", "value": "1"},
+                "a": {"y": [], "z": {"type": "bool", "value": "true"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(expected.diff_key_order(&expected), vec![]);
+        let differences = expected
+            .diff_key_order(&actual)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![r#"a: expected keys in order ["z", "y"], found ["y", "z"]"#.to_owned()]
+        );
+    }
+
+    #[test]
+    fn path_round_trip() {
+        for path in [
+            "a",
+            "a.b[3].c",
+            "[0][1]",
+            r#""key with space".b"#,
+            r#""a.b"[0]"#,
+            r#""""#,
+            r#""tab\t""#,
+        ] {
+            let parsed = path.parse::<DecodedPath>().unwrap();
+            assert_eq!(parsed.to_string(), path);
+        }
+        assert!("".parse::<DecodedPath>().unwrap().is_root());
+    }
+
+    #[test]
+    fn path_syntax() {
+        let path = r#"a . "b\u0063" . 'd\e'[ 2 ]"#.parse::<DecodedPath>().unwrap();
+        assert_eq!(
+            path.segments(),
+            [
+                PathSegment::Key("a".to_owned()),
+                PathSegment::Key("bc".to_owned()),
+                PathSegment::Key(r"d\e".to_owned()),
+                PathSegment::Index(2),
+            ]
+        );
+        for invalid in [
+            "a.", ".a", "a..b", "a[", "a[b]", "a b", r#""a"#, "a.[0]", "a!",
+        ] {
+            assert!(
+                invalid.parse::<DecodedPath>().is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn query() {
+        let value = DecodedValue::from_slice(
+            br#"{
+                "servers": {"alpha": {"ip": {"type": "string", "value": "10.0.0.1"}}},
+                "a.b": [{"type": "integer", "value": "1"}, {"c": []}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.query("servers.alpha.ip").unwrap(),
+            Some(&scalar(DecodedScalar::from("10.0.0.1")))
+        );
+        assert_eq!(
+            value.query(r#""a.b"[0]"#).unwrap(),
+            Some(&scalar(DecodedScalar::from(1)))
+        );
+        assert_eq!(
+            value.query(r#"'a.b'[1].c"#).unwrap(),
+            Some(&DecodedValue::Array(vec![]))
+        );
+        assert_eq!(value.query("servers.beta").unwrap(), None);
+        assert_eq!(value.query("servers[0]").unwrap(), None);
+        assert_eq!(value.query("").unwrap(), Some(&value));
+        assert!(value.query("servers.").is_err());
+    }
+
+    #[test]
+    fn leaves() {
+        let value = DecodedValue::from_slice(
+            br#"{
+                "a": {"b": [{"type": "integer", "value": "1"}, {"c": {"type": "bool", "value": "true"}}]},
+                "d": {"type": "string", "value": "x"},
+                "e": {}
+            }"#,
+        )
+        .unwrap();
+        let mut leaves = value
+            .leaves()
+            .map(|(path, s)| (path.to_string(), s.clone()))
+            .collect::<Vec<_>>();
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            leaves,
+            vec![
+                ("a.b[0]".to_owned(), DecodedScalar::from(1)),
+                ("a.b[1].c".to_owned(), DecodedScalar::from(true)),
+                ("d".to_owned(), DecodedScalar::from("x")),
+            ]
+        );
+        for (path, leaf) in value.leaves() {
+            assert_eq!(
+                value.get_path(&path),
+                Some(&DecodedValue::Scalar(leaf.clone()))
+            );
+        }
+    }
+
+    #[test]
+    fn diff_root_type() {
+        let expected = table([]);
+        let actual = DecodedValue::Array(vec![]);
+        let differences = expected
+            .diff(&actual)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec!["<root>: expected table with 0 keys, found array with 0 elements".to_owned()]
+        );
+    }
+
+    #[test]
+    fn normalized_scalars() {
+        let cases = [
+            (DecodedScalar::Integer("+0_1_000".to_owned()), "1000"),
+            (DecodedScalar::Integer("-0".to_owned()), "0"),
+            (
+                DecodedScalar::Integer("99999999999999999999".to_owned()),
+                "99999999999999999999",
+            ),
+            (DecodedScalar::Float("1e3".to_owned()), "1000.0"),
+            (DecodedScalar::Float("0.100".to_owned()), "0.1"),
+            (DecodedScalar::Float("-0.0".to_owned()), "-0.0"),
+            (DecodedScalar::Float("+inf".to_owned()), "inf"),
+            (DecodedScalar::Float("-inf".to_owned()), "-inf"),
+            (DecodedScalar::Float("+nan".to_owned()), "nan"),
+            (DecodedScalar::Float("-nan".to_owned()), "-nan"),
+            (DecodedScalar::Bool("true".to_owned()), "true"),
+            (DecodedScalar::String(" 1e3 ".to_owned()), " 1e3 "),
+            (
+                DecodedScalar::Datetime("1979-05-27t07:32:00.500z".to_owned()),
+                "1979-05-27T07:32:00.5Z",
+            ),
+            (
+                DecodedScalar::Datetime("1979-05-27 07:32-07:00".to_owned()),
+                "1979-05-27T07:32:00-07:00",
+            ),
+            (
+                DecodedScalar::DatetimeLocal("1979-05-27 07:32:00.000".to_owned()),
+                "1979-05-27T07:32:00",
+            ),
+            (
+                DecodedScalar::DateLocal("1979-05-27".to_owned()),
+                "1979-05-27",
+            ),
+            (DecodedScalar::TimeLocal("07:32".to_owned()), "07:32:00"),
+            (
+                DecodedScalar::TimeLocal("00:32:00.999999000".to_owned()),
+                "00:32:00.999999",
+            ),
+        ];
+        for (scalar, expected) in cases {
+            let normalized = scalar.normalized().unwrap();
+            assert_eq!(normalized.type_name(), scalar.type_name());
+            assert_eq!(normalized.as_str(), expected, "{scalar:?}");
+            assert_eq!(normalized.normalized().unwrap().as_str(), expected);
+        }
+
+        for scalar in [
+            DecodedScalar::Integer("0x10".to_owned()),
+            DecodedScalar::Float("1.0.0".to_owned()),
+            DecodedScalar::Bool("yes".to_owned()),
+            DecodedScalar::TimeLocal("07:32:00.".to_owned()),
+            DecodedScalar::DateLocal("1979-02-30".to_owned()),
+        ] {
+            assert!(scalar.normalized().is_err(), "{scalar:?}");
+        }
+    }
+
+    #[test]
+    fn normalized_values() {
+        let value = DecodedValue::from_slice(
+            br#"{
+                "a": [{"type": "integer", "value": "0_1"}, {"b": {"type": "float", "value": "1E1"}}],
+                "c": {}
+            }"#,
+        )
+        .unwrap();
+        let normalized = value.normalized().unwrap();
+        let leaves = |v: &DecodedValue| {
+            let mut leaves = v
+                .leaves()
+                .map(|(path, s)| (path.to_string(), s.as_str().to_owned()))
+                .collect::<Vec<_>>();
+            leaves.sort();
+            leaves
+        };
+        assert_eq!(
+            leaves(&normalized),
+            vec![
+                ("a[0]".to_owned(), "1".to_owned()),
+                ("a[1].b".to_owned(), "10.0".to_owned()),
+            ]
+        );
+        assert_eq!(normalized.query("c").unwrap(), value.query("c").unwrap());
+
+        let value =
+            DecodedValue::from_slice(br#"{"a": [{"b": {"type": "bool", "value": "True"}}]}"#)
+                .unwrap();
+        assert_eq!(
+            value.normalized().unwrap_err().to_string(),
+            r#"cannot normalize a[0].b: invalid bool "True""#
+        );
+    }
+
+    fn hash_of(value: &impl std::hash::Hash) -> u64 {
+        use std::hash::Hasher as _;
+        let mut hasher = std::hash::DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn scalar_hash_and_ord() {
+        let equal_groups = [
+            vec![
+                DecodedScalar::Integer("1000".to_owned()),
+                DecodedScalar::Integer("+1_000".to_owned()),
+                DecodedScalar::Integer("01000".to_owned()),
+            ],
+            vec![
+                DecodedScalar::Float("inf".to_owned()),
+                DecodedScalar::Float("+inf".to_owned()),
+            ],
+            vec![
+                DecodedScalar::Float("nan".to_owned()),
+                DecodedScalar::Float("-nan".to_owned()),
+            ],
+            vec![
+                DecodedScalar::Float("0.0".to_owned()),
+                DecodedScalar::Float("-0.0".to_owned()),
+            ],
+            vec![
+                DecodedScalar::Datetime("1979-05-27T07:32:00Z".to_owned()),
+                DecodedScalar::Datetime("1979-05-27 00:32:00-07:00".to_owned()),
+            ],
+            vec![
+                DecodedScalar::TimeLocal("07:32:00.5".to_owned()),
+                DecodedScalar::TimeLocal("07:32:00.500".to_owned()),
+            ],
+        ];
+        for group in &equal_groups {
+            for a in group {
+                for b in group {
+                    assert_eq!(a, b);
+                    assert_eq!(a.cmp(b), std::cmp::Ordering::Equal, "{a:?} {b:?}");
+                    assert_eq!(hash_of(a), hash_of(b), "{a:?} {b:?}");
+                }
+            }
+        }
+
+        let mut sorted = [
+            DecodedScalar::Float("nan".to_owned()),
+            DecodedScalar::Integer("99999999999999999999".to_owned()),
+            DecodedScalar::Float("inf".to_owned()),
+            DecodedScalar::Integer("-2".to_owned()),
+            DecodedScalar::String("b".to_owned()),
+            DecodedScalar::Float("-inf".to_owned()),
+            DecodedScalar::Integer("10".to_owned()),
+            DecodedScalar::String("a".to_owned()),
+        ];
+        sorted.sort();
+        assert_eq!(
+            sorted.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+            [
+                "a",
+                "b",
+                "-2",
+                "10",
+                "99999999999999999999",
+                "-inf",
+                "inf",
+                "nan"
+            ]
+        );
+
+        let set = equal_groups
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(set.len(), equal_groups.len());
+        let set = equal_groups
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(set.len(), equal_groups.len());
+    }
+
+    #[test]
+    fn value_hash_and_ord() {
+        let a = DecodedValue::from_slice(
+            br#"{"x": {"type": "integer", "value": "1"}, "y": [{"type": "float", "value": "inf"}]}"#,
+        )
+        .unwrap();
+        let b = DecodedValue::from_slice(
+            br#"{"y": [{"type": "float", "value": "+inf"}], "x": {"type": "integer", "value": "+1"}}"#,
+        )
+        .unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        let scalar = scalar(DecodedScalar::from(1));
+        let table = table([("x", scalar.clone())]);
+        let array = DecodedValue::Array(vec![scalar.clone()]);
+        assert!(scalar < table);
+        assert!(table < array);
+        assert!(table < self::table([("y", scalar.clone())]));
+        assert!(self::table([]) < table);
+    }
+
+    #[test]
+    fn deterministic_json() {
+        let json = br#"{
+            "a": {"type": "integer", "value": "1"},
+            "b": {"c": {"type": "bool", "value": "true"}, "d": []},
+            "e": {}, "f": {}, "g": {}, "h": {}, "i": {}, "j": {}
+        }"#;
+        let value = DecodedValue::from_slice(json).unwrap();
+        assert_eq!(
+            value.to_string_compact().unwrap(),
+            r#"{"a":{"type":"integer","value":"1"},"b":{"c":{"type":"bool","value":"true"},"d":[]},"e":{},"f":{},"g":{},"h":{},"i":{},"j":{}}"#
+        );
+        let pretty = value.to_string_pretty().unwrap();
+        assert!(pretty.starts_with("{\n  \"a\": {\n"), "{pretty}");
+        assert_eq!(
+            DecodedValue::from_slice(pretty.as_bytes())
+                .unwrap()
+                .to_string_pretty()
+                .unwrap(),
+            pretty
+        );
+    }
 }
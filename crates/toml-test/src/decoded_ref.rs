@@ -0,0 +1,361 @@
+use std::borrow::Cow;
+
+use crate::compare::scalar_eq;
+use crate::decoded::ScalarType;
+
+/// Borrowed, zero-copy form of [`DecodedValue`][crate::DecodedValue]
+///
+/// Strings are borrowed from the input when they don't need unescaping.
+/// This compares equal to a [`DecodedValue`][crate::DecodedValue] using the same rules as
+/// [`DecodedScalar`][crate::DecodedScalar].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum DecodedValueRef<'a> {
+    Scalar(#[serde(borrow)] DecodedScalarRef<'a>),
+    Table(
+        #[serde(
+            borrow,
+            deserialize_with = "borrow_table",
+            serialize_with = "serialize_table"
+        )]
+        DecodedTableRef<'a>,
+    ),
+    Array(#[serde(borrow)] Vec<DecodedValueRef<'a>>),
+}
+
+/// Table of [`DecodedValueRef`]s
+///
+/// With the `preserve_order` feature, keys are kept in the order they were inserted / read.
+#[cfg(not(feature = "preserve_order"))]
+pub type DecodedTableRef<'a> = std::collections::HashMap<Cow<'a, str>, DecodedValueRef<'a>>;
+
+/// Table of [`DecodedValueRef`]s
+///
+/// With the `preserve_order` feature, keys are kept in the order they were inserted / read.
+#[cfg(feature = "preserve_order")]
+pub type DecodedTableRef<'a> = indexmap::IndexMap<Cow<'a, str>, DecodedValueRef<'a>>;
+
+/// Write keys in the same order as [`DecodedValue::to_string_pretty`][crate::DecodedValue::to_string_pretty]
+fn serialize_table<S>(table: &DecodedTableRef<'_>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    #[cfg(not(feature = "preserve_order"))]
+    {
+        let mut entries = table.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(k, _)| *k);
+        serializer.collect_map(entries)
+    }
+    #[cfg(feature = "preserve_order")]
+    {
+        serializer.collect_map(table)
+    }
+}
+
+fn borrow_table<'de, D>(deserializer: D) -> Result<DecodedTableRef<'de>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct TableVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for TableVisitor {
+        type Value = DecodedTableRef<'de>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            formatter.write_str("a table")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            let mut table = DecodedTableRef::default();
+            while let Some(key) = map.next_key_seed(KeySeed)? {
+                let value = map.next_value()?;
+                table.insert(key, value);
+            }
+            Ok(table)
+        }
+    }
+
+    deserializer.deserialize_map(TableVisitor)
+}
+
+/// Borrow keys when possible as `HashMap<Cow<str>, _>` always allocates
+struct KeySeed;
+
+impl<'de> serde::de::DeserializeSeed<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a key")
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+impl<'a> DecodedValueRef<'a> {
+    pub fn from_slice(v: &'a [u8]) -> Result<Self, crate::Error> {
+        serde_json::from_slice(v).map_err(|e| {
+            crate::Error::new(format!(
+                "failed decoding: {}\n```json\n{}\n```",
+                e,
+                String::from_utf8_lossy(v)
+            ))
+            .with_kind(crate::ErrorKind::Protocol)
+            .with_source(e)
+        })
+    }
+
+    pub fn into_owned(self) -> crate::DecodedValue {
+        match self {
+            DecodedValueRef::Scalar(s) => crate::DecodedValue::Scalar(s.into_owned()),
+            DecodedValueRef::Table(t) => crate::DecodedValue::Table(
+                t.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            ),
+            DecodedValueRef::Array(a) => {
+                crate::DecodedValue::Array(a.into_iter().map(|v| v.into_owned()).collect())
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a crate::DecodedValue> for DecodedValueRef<'a> {
+    fn from(other: &'a crate::DecodedValue) -> Self {
+        match other {
+            crate::DecodedValue::Scalar(s) => DecodedValueRef::Scalar(s.into()),
+            crate::DecodedValue::Table(t) => DecodedValueRef::Table(
+                t.iter()
+                    .map(|(k, v)| (Cow::Borrowed(k.as_str()), v.into()))
+                    .collect(),
+            ),
+            crate::DecodedValue::Array(a) => {
+                DecodedValueRef::Array(a.iter().map(|v| v.into()).collect())
+            }
+        }
+    }
+}
+
+impl PartialEq for DecodedValueRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DecodedValueRef::Scalar(s), DecodedValueRef::Scalar(o)) => s == o,
+            (DecodedValueRef::Table(s), DecodedValueRef::Table(o)) => {
+                s.len() == o.len()
+                    && s.iter()
+                        .all(|(k, v)| o.get(k.as_ref()).map(|o| v == o).unwrap_or(false))
+            }
+            (DecodedValueRef::Array(s), DecodedValueRef::Array(o)) => s == o,
+            (_, _) => false,
+        }
+    }
+}
+
+impl Eq for DecodedValueRef<'_> {}
+
+impl PartialEq<crate::DecodedValue> for DecodedValueRef<'_> {
+    fn eq(&self, other: &crate::DecodedValue) -> bool {
+        match (self, other) {
+            (DecodedValueRef::Scalar(s), crate::DecodedValue::Scalar(o)) => s == o,
+            (DecodedValueRef::Table(s), crate::DecodedValue::Table(o)) => {
+                s.len() == o.len()
+                    && s.iter()
+                        .all(|(k, v)| o.get(k.as_ref()).map(|o| v == o).unwrap_or(false))
+            }
+            (DecodedValueRef::Array(s), crate::DecodedValue::Array(o)) => {
+                s.len() == o.len() && s.iter().zip(o).all(|(s, o)| s == o)
+            }
+            (_, _) => false,
+        }
+    }
+}
+
+impl PartialEq<DecodedValueRef<'_>> for crate::DecodedValue {
+    fn eq(&self, other: &DecodedValueRef<'_>) -> bool {
+        other == self
+    }
+}
+
+/// A part of [`DecodedValueRef`]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(tag = "type", content = "value")]
+pub enum DecodedScalarRef<'a> {
+    String(#[serde(borrow)] Cow<'a, str>),
+    Integer(#[serde(borrow)] Cow<'a, str>),
+    Float(#[serde(borrow)] Cow<'a, str>),
+    Bool(#[serde(borrow)] Cow<'a, str>),
+    Datetime(#[serde(borrow)] Cow<'a, str>),
+    DatetimeLocal(#[serde(borrow)] Cow<'a, str>),
+    DateLocal(#[serde(borrow)] Cow<'a, str>),
+    TimeLocal(#[serde(borrow)] Cow<'a, str>),
+}
+
+impl DecodedScalarRef<'_> {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DecodedScalarRef::String(v)
+            | DecodedScalarRef::Integer(v)
+            | DecodedScalarRef::Float(v)
+            | DecodedScalarRef::Bool(v)
+            | DecodedScalarRef::Datetime(v)
+            | DecodedScalarRef::DatetimeLocal(v)
+            | DecodedScalarRef::DateLocal(v)
+            | DecodedScalarRef::TimeLocal(v) => v.as_ref(),
+        }
+    }
+
+    pub fn into_owned(self) -> crate::DecodedScalar {
+        match self {
+            DecodedScalarRef::String(v) => crate::DecodedScalar::String(v.into_owned()),
+            DecodedScalarRef::Integer(v) => crate::DecodedScalar::Integer(v.into_owned()),
+            DecodedScalarRef::Float(v) => crate::DecodedScalar::Float(v.into_owned()),
+            DecodedScalarRef::Bool(v) => crate::DecodedScalar::Bool(v.into_owned()),
+            DecodedScalarRef::Datetime(v) => crate::DecodedScalar::Datetime(v.into_owned()),
+            DecodedScalarRef::DatetimeLocal(v) => {
+                crate::DecodedScalar::DatetimeLocal(v.into_owned())
+            }
+            DecodedScalarRef::DateLocal(v) => crate::DecodedScalar::DateLocal(v.into_owned()),
+            DecodedScalarRef::TimeLocal(v) => crate::DecodedScalar::TimeLocal(v.into_owned()),
+        }
+    }
+
+    fn scalar_type(&self) -> ScalarType {
+        match self {
+            DecodedScalarRef::String(_) => ScalarType::String,
+            DecodedScalarRef::Integer(_) => ScalarType::Integer,
+            DecodedScalarRef::Float(_) => ScalarType::Float,
+            DecodedScalarRef::Bool(_) => ScalarType::Bool,
+            DecodedScalarRef::Datetime(_) => ScalarType::Datetime,
+            DecodedScalarRef::DatetimeLocal(_) => ScalarType::DatetimeLocal,
+            DecodedScalarRef::DateLocal(_) => ScalarType::DateLocal,
+            DecodedScalarRef::TimeLocal(_) => ScalarType::TimeLocal,
+        }
+    }
+}
+
+impl<'a> From<&'a crate::DecodedScalar> for DecodedScalarRef<'a> {
+    fn from(other: &'a crate::DecodedScalar) -> Self {
+        let v = Cow::Borrowed(other.as_str());
+        match other {
+            crate::DecodedScalar::String(_) => DecodedScalarRef::String(v),
+            crate::DecodedScalar::Integer(_) => DecodedScalarRef::Integer(v),
+            crate::DecodedScalar::Float(_) => DecodedScalarRef::Float(v),
+            crate::DecodedScalar::Bool(_) => DecodedScalarRef::Bool(v),
+            crate::DecodedScalar::Datetime(_) => DecodedScalarRef::Datetime(v),
+            crate::DecodedScalar::DatetimeLocal(_) => DecodedScalarRef::DatetimeLocal(v),
+            crate::DecodedScalar::DateLocal(_) => DecodedScalarRef::DateLocal(v),
+            crate::DecodedScalar::TimeLocal(_) => DecodedScalarRef::TimeLocal(v),
+        }
+    }
+}
+
+impl PartialEq for DecodedScalarRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        let ty = self.scalar_type();
+        ty == other.scalar_type() && scalar_eq(ty, self.as_str(), other.as_str())
+    }
+}
+
+impl Eq for DecodedScalarRef<'_> {}
+
+impl PartialEq<crate::DecodedScalar> for DecodedScalarRef<'_> {
+    fn eq(&self, other: &crate::DecodedScalar) -> bool {
+        let ty = self.scalar_type();
+        ty == other.scalar_type() && scalar_eq(ty, self.as_str(), other.as_str())
+    }
+}
+
+impl PartialEq<DecodedScalarRef<'_>> for crate::DecodedScalar {
+    fn eq(&self, other: &DecodedScalarRef<'_>) -> bool {
+        other == self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn borrows_unescaped_strings() {
+        let json = br#"{
+            "key": {"type": "string", "value": "plain"},
+            "esc\u0061ped": {"type": "string", "value": "a\nb"}
+        }"#;
+        let value = DecodedValueRef::from_slice(json).unwrap();
+        let DecodedValueRef::Table(table) = &value else {
+            panic!("expected table: {value:?}");
+        };
+        let (key, plain) = table.get_key_value("key").unwrap();
+        assert!(matches!(key, Cow::Borrowed(_)));
+        assert!(matches!(
+            plain,
+            DecodedValueRef::Scalar(DecodedScalarRef::String(Cow::Borrowed("plain")))
+        ));
+        let (key, escaped) = table.get_key_value("escaped").unwrap();
+        assert!(matches!(key, Cow::Owned(_)));
+        assert!(matches!(
+            escaped,
+            DecodedValueRef::Scalar(DecodedScalarRef::String(Cow::Owned(_)))
+        ));
+    }
+
+    #[test]
+    fn equals_owned() {
+        let json = br#"{
+            "a": [{"type": "float", "value": "+inf"}, {"type": "integer", "value": "1"}],
+            "b": {"c": {"type": "datetime", "value": "1987-07-05t17:45:00z"}}
+        }"#;
+        let borrowed = DecodedValueRef::from_slice(json).unwrap();
+        let owned = crate::DecodedValue::from_slice(json).unwrap();
+        assert_eq!(borrowed, owned);
+        assert_eq!(owned, borrowed);
+        assert_eq!(borrowed.clone().into_owned(), owned);
+        assert_eq!(DecodedValueRef::from(&owned), borrowed);
+
+        let other = br#"{
+            "a": [{"type": "float", "value": "inf"}, {"type": "integer", "value": "1"}],
+            "b": {"c": {"type": "datetime", "value": "1987-07-05 17:45:00Z"}}
+        }"#;
+        assert_eq!(DecodedValueRef::from_slice(other).unwrap(), owned);
+
+        let different = br#"{
+            "a": [{"type": "float", "value": "inf"}, {"type": "float", "value": "1"}],
+            "b": {"c": {"type": "datetime", "value": "1987-07-05 17:45:00Z"}}
+        }"#;
+        assert_ne!(DecodedValueRef::from_slice(different).unwrap(), owned);
+
+        let err = DecodedValueRef::from_slice(b"{").unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Protocol);
+        assert!(
+            err.to_string()
+                .starts_with("failed decoding: EOF while parsing an object"),
+            "{err}"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+        assert_eq!(err, crate::DecodedValue::from_slice(b"{").unwrap_err());
+    }
+}
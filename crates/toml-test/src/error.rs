@@ -1,14 +1,82 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Error {
+    kind: ErrorKind,
     message: String,
+    span: Option<std::ops::Range<usize>>,
+    line_column: Option<(usize, usize)>,
+    source: Option<std::sync::Arc<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Error {
     pub fn new(message: impl ToString) -> Self {
         Self {
+            kind: ErrorKind::Other,
             message: message.to_string(),
+            span: None,
+            line_column: None,
+            source: None,
         }
     }
+
+    /// An [`ErrorKind::Io`] error, saying what failed, like `failed to read stdin`
+    ///
+    /// The details are left to the [source][std::error::Error::source].
+    pub fn io(message: impl ToString, err: std::io::Error) -> Self {
+        Self::new(message).with_kind(ErrorKind::Io).with_source(err)
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Record the underlying error, see [`std::error::Error::source`]
+    pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        self.source = Some(std::sync::Arc::new(source));
+        self
+    }
+
+    /// Record the byte span of the TOML document the error is about
+    pub fn with_span(mut self, span: std::ops::Range<usize>) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Record the line and column of the TOML document the error is about, starting at 1
+    ///
+    /// Columns count characters, not bytes.
+    pub fn with_line_column(mut self, line: usize, column: usize) -> Self {
+        self.line_column = Some((line, column));
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> Option<std::ops::Range<usize>> {
+        self.span.clone()
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line_column.map(|(line, _)| line)
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        self.line_column.map(|(_, column)| column)
+    }
+
+    /// The line and column, starting at 1, falling back to the start of the span within `input`
+    pub fn line_column_in(&self, input: &[u8]) -> Option<(usize, usize)> {
+        self.line_column.or_else(|| {
+            let location = crate::strict::Location::new(input, self.span.as_ref()?.start);
+            Some((location.line(), location.column()))
+        })
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -17,4 +85,143 @@ impl std::fmt::Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// The source is not compared
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+            && self.message == other.message
+            && self.span == other.span
+            && self.line_column == other.line_column
+    }
+}
+
+impl Eq for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::new(msg)
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::new(msg)
+    }
+}
+
+/// What went wrong, see [`Error::kind`]
+///
+/// Everything but [infrastructure failures][ErrorKind::is_infrastructure] is a conformance
+/// result, with [`Timeout`][ErrorKind::Timeout] and [`Panic`][ErrorKind::Panic] being
+/// [abnormal][ErrorKind::is_abnormal] rather than the implementation rejecting a case.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading or writing failed
+    Io,
+    /// The implementation could not be started
+    Spawn,
+    /// The implementation broke the toml-test protocol, like writing malformed tagged JSON or
+    /// exceeding the output limit
+    ///
+    /// This includes malformed test data.
+    Protocol,
+    /// The implementation produced a different value than expected
+    Mismatch,
+    /// The implementation accepted an invalid case
+    UnexpectedSuccess,
+    /// The implementation exceeded its time limit
+    Timeout,
+    /// The implementation panicked or crashed
+    Panic,
+    /// Any other error, like the implementation rejecting a case
+    Other,
+}
+
+impl ErrorKind {
+    /// Whether this is a failure to run a case rather than a conformance result
+    pub const fn is_infrastructure(self) -> bool {
+        match self {
+            Self::Io | Self::Spawn | Self::Protocol => true,
+            Self::Mismatch
+            | Self::UnexpectedSuccess
+            | Self::Timeout
+            | Self::Panic
+            | Self::Other => false,
+        }
+    }
+
+    /// Whether the implementation hung or crashed, failing the case without an answer
+    pub const fn is_abnormal(self) -> bool {
+        matches!(self, Self::Timeout | Self::Panic)
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Io => "I/O",
+            Self::Spawn => "spawn",
+            Self::Protocol => "protocol",
+            Self::Mismatch => "mismatch",
+            Self::UnexpectedSuccess => "unexpected success",
+            Self::Timeout => "timeout",
+            Self::Panic => "panic",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let err = Error::io("failed to read stdin", io);
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(err.kind().is_infrastructure());
+        assert_eq!(err.to_string(), "failed to read stdin");
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(
+            source.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        let err = Error::new("outer")
+            .with_kind(ErrorKind::Mismatch)
+            .with_source(err);
+        assert!(!err.kind().is_infrastructure());
+        let chain = std::iter::successors(Some(&err as &dyn std::error::Error), |err| err.source())
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(chain, ["outer", "failed to read stdin", "missing"]);
+        assert_eq!(err, Error::new("outer").with_kind(ErrorKind::Mismatch));
+        assert_ne!(err, Error::new("outer"));
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(ErrorKind::Spawn.is_infrastructure());
+        assert!(ErrorKind::Protocol.is_infrastructure());
+        for kind in [ErrorKind::Timeout, ErrorKind::Panic] {
+            assert!(!kind.is_infrastructure());
+            assert!(kind.is_abnormal());
+        }
+        assert!(!ErrorKind::Other.is_infrastructure());
+        assert!(!ErrorKind::Other.is_abnormal());
+    }
+}
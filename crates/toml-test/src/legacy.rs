@@ -0,0 +1,240 @@
+use crate::DecodedPath;
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::PathSegment;
+use crate::decoded::ScalarType;
+
+/// Which revision of the toml-test tagged JSON format to read / write
+///
+/// See [`DecodedValue::from_slice_with_format`] and [`Command::json_format`][crate::Command::json_format]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum JsonFormat {
+    /// The format of toml-test 1.0 and later, as modeled by [`DecodedValue`]
+    #[default]
+    Current,
+    /// The format of toml-test before 1.0
+    ///
+    /// - Arrays, except for arrays of tables, are wrapped as `{"type": "array", "value": [...]}`
+    /// - All datetimes have the `datetime` type
+    ///
+    /// When reading, the datetime types are inferred from the value and current types are
+    /// accepted as well.
+    Legacy,
+}
+
+pub(crate) fn from_slice(v: &[u8]) -> Result<DecodedValue, crate::Error> {
+    let value = serde_json::from_slice::<LegacyValue>(v).map_err(|e| {
+        crate::Error::new(format!(
+            "failed decoding: {}\n```json\n{}\n```",
+            e,
+            String::from_utf8_lossy(v)
+        ))
+        .with_kind(crate::ErrorKind::Protocol)
+        .with_source(e)
+    })?;
+    upgrade(&mut DecodedPath::root(), value)
+}
+
+pub(crate) fn to_string_pretty(value: &DecodedValue) -> Result<String, crate::Error> {
+    serde_json::to_string_pretty(&Legacy(value)).map_err(crate::Error::new)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum LegacyValue {
+    Tagged(Tagged),
+    Table(LegacyTable),
+    Array(Vec<LegacyValue>),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tagged {
+    #[serde(rename = "type")]
+    ty: String,
+    value: TaggedValue,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum TaggedValue {
+    Scalar(String),
+    Array(Vec<LegacyValue>),
+}
+
+#[cfg(not(feature = "preserve_order"))]
+type LegacyTable = std::collections::HashMap<String, LegacyValue>;
+
+#[cfg(feature = "preserve_order")]
+type LegacyTable = indexmap::IndexMap<String, LegacyValue>;
+
+fn upgrade(path: &mut DecodedPath, value: LegacyValue) -> Result<DecodedValue, crate::Error> {
+    let invalid = |path: &DecodedPath, message: String| {
+        crate::Error::new(format!("invalid legacy tagged JSON at {path}: {message}"))
+            .with_kind(crate::ErrorKind::Protocol)
+    };
+    match value {
+        LegacyValue::Tagged(Tagged {
+            ty,
+            value: TaggedValue::Array(items),
+        }) => {
+            if ty != "array" {
+                return Err(invalid(path, format!("`{ty}` cannot have an array value")));
+            }
+            upgrade_array(path, items)
+        }
+        LegacyValue::Tagged(Tagged {
+            ty,
+            value: TaggedValue::Scalar(value),
+        }) => {
+            if ty == "datetime" {
+                crate::ser::classify_datetime(&value)
+                    .map(DecodedValue::Scalar)
+                    .map_err(|err| invalid(path, err.to_string()))
+            } else if let Some(ty) = ScalarType::from_name(&ty) {
+                Ok(DecodedValue::Scalar(ty.scalar(value)))
+            } else {
+                Err(invalid(path, format!("unknown type `{ty}`")))
+            }
+        }
+        LegacyValue::Table(table) => {
+            let mut upgraded = DecodedTable::default();
+            for (key, value) in table {
+                path.push(PathSegment::Key(key.clone()));
+                upgraded.insert(key, upgrade(path, value)?);
+                path.pop();
+            }
+            Ok(DecodedValue::Table(upgraded))
+        }
+        LegacyValue::Array(items) => upgrade_array(path, items),
+    }
+}
+
+fn upgrade_array(
+    path: &mut DecodedPath,
+    items: Vec<LegacyValue>,
+) -> Result<DecodedValue, crate::Error> {
+    let mut upgraded = Vec::with_capacity(items.len());
+    for (i, item) in items.into_iter().enumerate() {
+        path.push(PathSegment::Index(i));
+        upgraded.push(upgrade(path, item)?);
+        path.pop();
+    }
+    Ok(DecodedValue::Array(upgraded))
+}
+
+/// Serialize a [`DecodedValue`] in the [`JsonFormat::Legacy`] format
+struct Legacy<'v>(&'v DecodedValue);
+
+impl serde::Serialize for Legacy<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap as _;
+
+        match self.0 {
+            DecodedValue::Scalar(scalar) => {
+                let ty = match scalar {
+                    DecodedScalar::Datetime(_)
+                    | DecodedScalar::DatetimeLocal(_)
+                    | DecodedScalar::DateLocal(_)
+                    | DecodedScalar::TimeLocal(_) => "datetime",
+                    _ => scalar.scalar_type().name(),
+                };
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("type", ty)?;
+                map.serialize_entry("value", scalar.as_str())?;
+                map.end()
+            }
+            DecodedValue::Table(table) => serializer.collect_map(
+                crate::decoded::ordered_entries(table)
+                    .into_iter()
+                    .map(|(k, v)| (k, Legacy(v))),
+            ),
+            DecodedValue::Array(items) => {
+                let is_array_of_tables =
+                    !items.is_empty() && items.iter().all(|i| matches!(i, DecodedValue::Table(_)));
+                let items = items.iter().map(Legacy).collect::<Vec<_>>();
+                if is_array_of_tables {
+                    serializer.collect_seq(items)
+                } else {
+                    let mut map = serializer.serialize_map(Some(2))?;
+                    map.serialize_entry("type", "array")?;
+                    map.serialize_entry("value", &items)?;
+                    map.end()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrade() {
+        let legacy = br#"{
+            "a": {"type": "array", "value": [
+                {"type": "integer", "value": "1"},
+                {"type": "array", "value": []}
+            ]},
+            "b": [{"c": {"type": "datetime", "value": "1979-05-27T07:32:00Z"}}],
+            "d": {"type": "datetime", "value": "1979-05-27T07:32:00"},
+            "e": {"type": "datetime", "value": "1979-05-27"},
+            "f": {"type": "datetime", "value": "07:32:00"},
+            "g": {"type": "date-local", "value": "1979-05-27"}
+        }"#;
+        let actual = from_slice(legacy).unwrap();
+        let expected = DecodedValue::from_slice(
+            br#"{
+                "a": [{"type": "integer", "value": "1"}, []],
+                "b": [{"c": {"type": "datetime", "value": "1979-05-27T07:32:00Z"}}],
+                "d": {"type": "datetime-local", "value": "1979-05-27T07:32:00"},
+                "e": {"type": "date-local", "value": "1979-05-27"},
+                "f": {"type": "time-local", "value": "07:32:00"},
+                "g": {"type": "date-local", "value": "1979-05-27"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(actual.diff(&expected), []);
+    }
+
+    #[test]
+    fn invalid() {
+        let err = from_slice(br#"{"a": [{"type": "datetime", "value": "noon"}]}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"invalid legacy tagged JSON at a[0]: invalid datetime "noon""#
+        );
+        let err = from_slice(br#"{"a": {"type": "color", "value": "red"}}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid legacy tagged JSON at a: unknown type `color`"
+        );
+        let err = from_slice(br#"{"a": {"type": "string", "value": []}}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid legacy tagged JSON at a: `string` cannot have an array value"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = DecodedValue::from_slice(
+            br#"{
+                "a": [{"type": "integer", "value": "1"}, [{"type": "time-local", "value": "07:32:00"}]],
+                "b": [{"c": {"type": "datetime-local", "value": "1979-05-27T07:32:00"}}],
+                "d": []
+            }"#,
+        )
+        .unwrap();
+        let legacy = to_string_pretty(&value).unwrap();
+        assert!(!legacy.contains("time-local"), "{legacy}");
+        assert!(!legacy.contains("datetime-local"), "{legacy}");
+        assert_eq!(from_slice(legacy.as_bytes()).unwrap().diff(&value), []);
+    }
+}
@@ -8,16 +8,70 @@
 #![warn(clippy::print_stderr)]
 #![warn(clippy::print_stdout)]
 
+mod adapter;
+mod batch;
+mod bin;
+mod command;
+mod compare;
+#[cfg(any(feature = "toml", feature = "toml_edit"))]
+mod compat;
+mod de;
 mod decoded;
+mod decoded_ref;
 mod error;
+mod legacy;
+mod macros;
+mod parser;
+mod ser;
+mod strict;
+#[cfg(feature = "toml")]
+mod toml_compat;
+#[cfg(feature = "toml_edit")]
+mod toml_edit_compat;
 mod verify;
+mod version;
+mod writer;
 
+pub use adapter::DecoderFn;
+pub use adapter::EncoderFn;
+pub use bin::BATCH_ENV;
+pub use bin::SPEC_VERSION_ENV;
+pub use bin::decoder_main;
+pub use bin::encoder_main;
+pub use command::Command;
+pub use command::StdinEncoding;
+pub use compare::ComparePolicy;
+pub use compare::ScalarMismatch;
+pub use de::from_decoded;
+pub use decoded::DecodedPath;
 pub use decoded::DecodedScalar;
+pub use decoded::DecodedTable;
 pub use decoded::DecodedValue;
+pub use decoded::Difference;
+pub use decoded::DifferenceKind;
+pub use decoded::Leaves;
+pub use decoded::PathSegment;
+pub use decoded_ref::DecodedScalarRef;
+pub use decoded_ref::DecodedTableRef;
+pub use decoded_ref::DecodedValueRef;
 pub use error::Error;
-pub use verify::Command;
+pub use error::ErrorKind;
+pub use legacy::JsonFormat;
+pub use parser::ReferenceDecoder;
+pub use ser::DATETIME_FIELD;
+pub use ser::DATETIME_STRUCT;
+pub use ser::DecodedSerializer;
+pub use ser::SerializeDecodedArray;
+pub use ser::SerializeDecodedTable;
+pub use ser::to_decoded;
+#[cfg(feature = "toml")]
+pub use toml_compat::Toml;
+#[cfg(feature = "toml_edit")]
+pub use toml_edit_compat::TomlEdit;
 pub use verify::Decoder;
 pub use verify::Encoder;
+pub use version::SpecVersion;
+pub use writer::ReferenceEncoder;
 
 #[doc = include_str!("../README.md")]
 #[cfg(doctest)]
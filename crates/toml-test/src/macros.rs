@@ -0,0 +1,121 @@
+/// Construct a [`DecodedValue`][crate::DecodedValue]
+///
+/// Tables are written as `{ "key": value, ... }` and arrays as `[value, ...]`.  Scalars name
+/// their type, calling the [`DecodedScalar`][crate::DecodedScalar] constructor of the same name,
+/// e.g. [`integer`][crate::DecodedScalar::integer] or
+/// [`date_local`][crate::DecodedScalar::date_local].
+///
+/// # Example
+///
+/// ```rust
+/// let value = toml_test::decoded!({
+///     "title": string("TOML Example"),
+///     "owner": {
+///         "dob": datetime("1979-05-27T07:32:00-08:00"),
+///     },
+///     "ports": [integer(8000), integer(8001)],
+///     "ratio": float(0.5),
+///     "enabled": bool(true),
+///     "products": [{ "name": string("Hammer") }, {}],
+/// });
+/// assert_eq!(
+///     value.query("owner.dob").unwrap(),
+///     Some(&toml_test::DecodedValue::Scalar(
+///         toml_test::DecodedScalar::Datetime("1979-05-27T07:32:00-08:00".to_owned())
+///     ))
+/// );
+/// ```
+#[macro_export]
+macro_rules! decoded {
+    ({ $($body:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut table = $crate::DecodedTable::default();
+        $crate::decoded!(@table table $($body)*);
+        $crate::DecodedValue::Table(table)
+    }};
+    ([ $($body:tt)* ]) => {
+        $crate::DecodedValue::Array($crate::decoded!(@array [] $($body)*))
+    };
+    ($ty:ident ( $($arg:tt)* )) => {
+        $crate::DecodedValue::Scalar($crate::DecodedScalar::$ty($($arg)*))
+    };
+
+    (@table $table:ident) => {};
+    (@table $table:ident $key:literal : { $($value:tt)* } $(, $($rest:tt)*)?) => {
+        $table.insert(::std::string::String::from($key), $crate::decoded!({ $($value)* }));
+        $crate::decoded!(@table $table $($($rest)*)?);
+    };
+    (@table $table:ident $key:literal : [ $($value:tt)* ] $(, $($rest:tt)*)?) => {
+        $table.insert(::std::string::String::from($key), $crate::decoded!([ $($value)* ]));
+        $crate::decoded!(@table $table $($($rest)*)?);
+    };
+    (@table $table:ident $key:literal : $ty:ident ( $($arg:tt)* ) $(, $($rest:tt)*)?) => {
+        $table.insert(::std::string::String::from($key), $crate::decoded!($ty ( $($arg)* )));
+        $crate::decoded!(@table $table $($($rest)*)?);
+    };
+
+    (@array [$($item:expr,)*]) => {
+        ::std::vec![$($item),*]
+    };
+    (@array [$($item:expr,)*] { $($value:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::decoded!(@array [$($item,)* $crate::decoded!({ $($value)* }),] $($($rest)*)?)
+    };
+    (@array [$($item:expr,)*] [ $($value:tt)* ] $(, $($rest:tt)*)?) => {
+        $crate::decoded!(@array [$($item,)* $crate::decoded!([ $($value)* ]),] $($($rest)*)?)
+    };
+    (@array [$($item:expr,)*] $ty:ident ( $($arg:tt)* ) $(, $($rest:tt)*)?) => {
+        $crate::decoded!(@array [$($item,)* $crate::decoded!($ty ( $($arg)* )),] $($($rest)*)?)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::DecodedScalar;
+    use crate::DecodedValue;
+
+    #[test]
+    fn decoded() {
+        let actual = decoded!({
+            "a": string("x"),
+            "b": integer(-1),
+            "c": float(f64::INFINITY),
+            "d": bool(false),
+            "e": datetime("1979-05-27T07:32:00Z"),
+            "f": datetime_local("1979-05-27T07:32:00"),
+            "g": date_local("1979-05-27"),
+            "h": time_local("07:32:00"),
+            "i": { "j": [integer(1), [], {}, [string("y")]] },
+            "k": [{ "l": {} }, {}],
+            "m": {}
+        });
+        let expected = DecodedValue::from_slice(
+            br#"{
+                "a": {"type": "string", "value": "x"},
+                "b": {"type": "integer", "value": "-1"},
+                "c": {"type": "float", "value": "inf"},
+                "d": {"type": "bool", "value": "false"},
+                "e": {"type": "datetime", "value": "1979-05-27T07:32:00Z"},
+                "f": {"type": "datetime-local", "value": "1979-05-27T07:32:00"},
+                "g": {"type": "date-local", "value": "1979-05-27"},
+                "h": {"type": "time-local", "value": "07:32:00"},
+                "i": {"j": [{"type": "integer", "value": "1"}, [], {}, [{"type": "string", "value": "y"}]]},
+                "k": [{"l": {}}, {}],
+                "m": {}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(actual.diff(&expected), []);
+        assert_eq!(decoded!({}), DecodedValue::Table(Default::default()));
+        assert_eq!(decoded!([]), DecodedValue::Array(vec![]));
+        assert_eq!(
+            decoded!(integer(1)),
+            DecodedValue::Scalar(DecodedScalar::from(1))
+        );
+    }
+
+    #[test]
+    #[should_panic = "invalid date-local"]
+    fn invalid_scalar() {
+        decoded!({ "a": date_local("1979-02-30") });
+    }
+}
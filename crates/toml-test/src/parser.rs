@@ -0,0 +1,1276 @@
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::SpecVersion;
+use crate::strict::Location;
+
+/// Spec-conformant TOML parser, for when you need a [`Decoder`][crate::Decoder] you can trust
+///
+/// Besides serving as the fixture for [`Encoder::verify_valid_case`][crate::Encoder::verify_valid_case],
+/// this can generate the expected JSON for a new test case:
+/// ```rust
+/// # use toml_test::Decoder as _;
+/// let decoder = toml_test::ReferenceDecoder::new(toml_test::SpecVersion::V1_0_0);
+/// let decoded = decoder.decode(b"key = 1979-05-27").unwrap();
+/// let expected = decoded.to_string_pretty().unwrap();
+/// ```
+///
+/// Scalars are normalized the way `toml-test` writes them, e.g. integers are always decimal
+/// and datetimes always have seconds and use `T` as the separator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReferenceDecoder {
+    version: SpecVersion,
+}
+
+impl ReferenceDecoder {
+    pub const fn new(version: SpecVersion) -> Self {
+        Self { version }
+    }
+
+    pub const fn version(&self) -> SpecVersion {
+        self.version
+    }
+
+    /// Parse a TOML document
+    pub fn parse(&self, input: &str) -> Result<DecodedValue, crate::Error> {
+        let body = input.strip_prefix('\u{feff}').unwrap_or(input);
+        let offset = input.len() - body.len();
+        let mut parser = Parser {
+            input,
+            pos: offset,
+            version: self.version,
+            root: Table::new(TableState::Header),
+            current: Vec::new(),
+            depth: 0,
+        };
+        match parser.document() {
+            Ok(()) => Ok(DecodedValue::Table(parser.root.into_decoded())),
+            Err(err) => {
+                let location = Location::new(input.as_bytes(), err.offset);
+                Err(
+                    crate::Error::new(format!("TOML parse error at {location}: {}", err.message))
+                        .with_span(err.offset..err.offset)
+                        .with_line_column(location.line(), location.column()),
+                )
+            }
+        }
+    }
+}
+
+impl crate::Decoder for ReferenceDecoder {
+    fn decode(&self, data: &[u8]) -> Result<DecodedValue, crate::Error> {
+        let input = std::str::from_utf8(data).map_err(|err| {
+            let start = err.valid_up_to();
+            let end = start + err.error_len().unwrap_or(0);
+            let location = Location::new(data, start);
+            crate::Error::new(format!("TOML parse error at {location}: {err}"))
+                .with_span(start..end)
+                .with_line_column(location.line(), location.column())
+        })?;
+        self.parse(input)
+    }
+
+    fn name(&self) -> &str {
+        "toml-test"
+    }
+}
+
+struct ParseError {
+    offset: usize,
+    message: String,
+}
+
+type PResult<T> = Result<T, ParseError>;
+
+enum Node {
+    Scalar(DecodedScalar),
+    /// Static array, from an array literal
+    Array(Vec<Node>),
+    Table(Table),
+    /// From `[[header]]`s
+    ArrayOfTables(Vec<Table>),
+}
+
+impl Node {
+    fn description(&self) -> &'static str {
+        match self {
+            Node::Scalar(_) => "value",
+            Node::Array(_) => "array",
+            Node::Table(table) => match table.state {
+                TableState::Implicit | TableState::Header => "table",
+                TableState::Dotted => "table defined by dotted keys",
+                TableState::Inline => "inline table",
+            },
+            Node::ArrayOfTables(_) => "array of tables",
+        }
+    }
+
+    fn into_decoded(self) -> DecodedValue {
+        match self {
+            Node::Scalar(scalar) => DecodedValue::Scalar(scalar),
+            Node::Array(items) => {
+                DecodedValue::Array(items.into_iter().map(Node::into_decoded).collect())
+            }
+            Node::Table(table) => DecodedValue::Table(table.into_decoded()),
+            Node::ArrayOfTables(tables) => DecodedValue::Array(
+                tables
+                    .into_iter()
+                    .map(|t| DecodedValue::Table(t.into_decoded()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// How a table came to be, which determines how it may be extended
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TableState {
+    /// Created as the parent of a `[header]`, may still be defined by its own header
+    Implicit,
+    /// Defined by a `[header]`
+    Header,
+    /// Created by a dotted key, may only be extended by dotted keys in the same section
+    Dotted,
+    /// Frozen
+    Inline,
+}
+
+struct Table {
+    state: TableState,
+    entries: Vec<(String, Node)>,
+    index: std::collections::HashMap<String, usize>,
+}
+
+impl Table {
+    fn new(state: TableState) -> Self {
+        Self {
+            state,
+            entries: Vec::new(),
+            index: Default::default(),
+        }
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Node> {
+        let i = *self.index.get(key)?;
+        Some(&mut self.entries[i].1)
+    }
+
+    fn insert(&mut self, key: String, node: Node) -> &mut Node {
+        let i = self.entries.len();
+        self.index.insert(key.clone(), i);
+        self.entries.push((key, node));
+        &mut self.entries[i].1
+    }
+
+    fn get_or_insert(&mut self, key: &str, state: TableState) -> &mut Node {
+        match self.index.get(key) {
+            Some(&i) => &mut self.entries[i].1,
+            None => self.insert(key.to_owned(), Node::Table(Table::new(state))),
+        }
+    }
+
+    fn into_decoded(self) -> DecodedTable {
+        self.entries
+            .into_iter()
+            .map(|(k, v)| (k, v.into_decoded()))
+            .collect()
+    }
+}
+
+type Key = (String, usize);
+
+/// How deeply dotted keys, arrays and inline tables may nest within a header or key-value pair
+///
+/// This bounds the recursion of [`Parser`] for untrusted input.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'i> {
+    input: &'i str,
+    pos: usize,
+    version: SpecVersion,
+    root: Table,
+    /// Keys of the most recent `[header]` / `[[header]]`
+    current: Vec<String>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: impl Into<String>) -> PResult<T> {
+        Err(self.error_at(self.pos, message))
+    }
+
+    fn error_at(&self, offset: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_byte(&self, n: usize) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + n).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> PResult<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{c}`"))
+        }
+    }
+
+    fn v1_1(&self) -> bool {
+        SpecVersion::V1_1_0 <= self.version
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t') = self.peek_byte(0) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume a newline, if present
+    fn newline(&mut self) -> PResult<bool> {
+        match self.peek_byte(0) {
+            Some(b'\n') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(b'\r') if self.peek_byte(1) == Some(b'\n') => {
+                self.pos += 2;
+                Ok(true)
+            }
+            Some(b'\r') => self.error("carriage return must be followed by a line feed"),
+            _ => Ok(false),
+        }
+    }
+
+    fn comment(&mut self) -> PResult<()> {
+        if !self.eat('#') {
+            return Ok(());
+        }
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' => break,
+                '\r' if self.peek_byte(1) == Some(b'\n') => break,
+                c if is_disallowed_control(c) => {
+                    return self.error(format!("control character {c:?} in comment"));
+                }
+                c => self.pos += c.len_utf8(),
+            }
+        }
+        Ok(())
+    }
+
+    /// Skip any whitespace, comments, and newlines
+    fn skip_blank(&mut self) -> PResult<()> {
+        loop {
+            self.skip_ws();
+            self.comment()?;
+            if !self.newline()? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn document(&mut self) -> PResult<()> {
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                None => return Ok(()),
+                Some('#' | '\n' | '\r') => {}
+                Some('[') => self.header()?,
+                Some(_) => {
+                    let (keys, node) = self.keyval()?;
+                    let table = self.current_table();
+                    insert_dotted(table, keys, node)?;
+                }
+            }
+            self.skip_ws();
+            self.comment()?;
+            if !self.newline()? && self.peek().is_some() {
+                return self.error("expected newline after expression");
+            }
+        }
+    }
+
+    fn current_table(&mut self) -> &mut Table {
+        let mut table = &mut self.root;
+        for key in &self.current {
+            table = match table.get_mut(key) {
+                Some(Node::Table(child)) => child,
+                Some(Node::ArrayOfTables(tables)) => {
+                    tables.last_mut().expect("arrays of tables are never empty")
+                }
+                _ => unreachable!("headers always resolve to a table"),
+            };
+        }
+        table
+    }
+
+    fn header(&mut self) -> PResult<()> {
+        self.expect('[')?;
+        let array = self.eat('[');
+        self.skip_ws();
+        let keys = self.key()?;
+        self.skip_ws();
+        self.expect(']')?;
+        if array && !self.eat(']') {
+            return self.error("expected `]]`");
+        }
+
+        let (last, parents) = keys.split_last().expect("keys are never empty");
+        let mut table = &mut self.root;
+        for (key, offset) in parents {
+            let node = table.get_or_insert(key, TableState::Implicit);
+            let extendable = match node {
+                Node::Table(child) => child.state != TableState::Inline,
+                Node::ArrayOfTables(_) => true,
+                Node::Scalar(_) | Node::Array(_) => false,
+            };
+            if !extendable {
+                let message = format!("`{key}` is already defined as {}", a(node.description()));
+                return Err(ParseError {
+                    offset: *offset,
+                    message,
+                });
+            }
+            table = match node {
+                Node::Table(child) => child,
+                Node::ArrayOfTables(tables) => {
+                    tables.last_mut().expect("arrays of tables are never empty")
+                }
+                Node::Scalar(_) | Node::Array(_) => unreachable!("checked above"),
+            };
+        }
+        let (key, offset) = last;
+        match (table.get_mut(key), array) {
+            (None, false) => {
+                table.insert(key.clone(), Node::Table(Table::new(TableState::Header)));
+            }
+            (None, true) => {
+                let tables = vec![Table::new(TableState::Header)];
+                table.insert(key.clone(), Node::ArrayOfTables(tables));
+            }
+            (Some(Node::Table(child)), false) if child.state == TableState::Implicit => {
+                child.state = TableState::Header;
+            }
+            (Some(Node::ArrayOfTables(tables)), true) => {
+                tables.push(Table::new(TableState::Header));
+            }
+            (Some(node), _) => {
+                let message = format!("`{key}` is already defined as {}", a(node.description()));
+                return Err(ParseError {
+                    offset: *offset,
+                    message,
+                });
+            }
+        }
+        self.current = keys.into_iter().map(|(k, _)| k).collect();
+        Ok(())
+    }
+
+    fn keyval(&mut self) -> PResult<(Vec<Key>, Node)> {
+        let keys = self.key()?;
+        self.skip_ws();
+        self.expect('=')?;
+        self.skip_ws();
+        // Dotted keys count towards the depth of their value
+        self.depth += keys.len();
+        let value = self.value();
+        self.depth -= keys.len();
+        Ok((keys, value?))
+    }
+
+    /// A possibly dotted key
+    fn key(&mut self) -> PResult<Vec<Key>> {
+        let mut keys = Vec::new();
+        loop {
+            let offset = self.pos;
+            let key = match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    self.literal_string()?
+                }
+                Some(c) if is_bare_key_char(c) => {
+                    let len = self
+                        .rest()
+                        .find(|c| !is_bare_key_char(c))
+                        .unwrap_or(self.rest().len());
+                    let key = self.rest()[..len].to_owned();
+                    self.pos += len;
+                    key
+                }
+                _ => return self.error("expected a key"),
+            };
+            if MAX_DEPTH <= self.depth + keys.len() {
+                return Err(self.error_at(offset, format!("nested deeper than {MAX_DEPTH} levels")));
+            }
+            keys.push((key, offset));
+            self.skip_ws();
+            if !self.eat('.') {
+                return Ok(keys);
+            }
+            self.skip_ws();
+        }
+    }
+
+    fn value(&mut self) -> PResult<Node> {
+        let scalar = match self.peek() {
+            Some('"') if self.rest().starts_with(r#"""""#) => {
+                self.pos += 3;
+                DecodedScalar::String(self.ml_basic_string()?)
+            }
+            Some('"') => {
+                self.pos += 1;
+                DecodedScalar::String(self.basic_string()?)
+            }
+            Some('\'') if self.rest().starts_with("'''") => {
+                self.pos += 3;
+                DecodedScalar::String(self.ml_literal_string()?)
+            }
+            Some('\'') => {
+                self.pos += 1;
+                DecodedScalar::String(self.literal_string()?)
+            }
+            Some('[') => return self.nested(Self::array),
+            Some('{') => return self.nested(Self::inline_table),
+            Some('t') if self.rest().starts_with("true") => {
+                self.pos += 4;
+                DecodedScalar::from(true)
+            }
+            Some('f') if self.rest().starts_with("false") => {
+                self.pos += 5;
+                DecodedScalar::from(false)
+            }
+            Some('0'..='9') if self.is_date() || self.is_time() => self.datetime()?,
+            Some('0'..='9' | '+' | '-' | 'i' | 'n') => self.number()?,
+            _ => return self.error("expected a value"),
+        };
+        Ok(Node::Scalar(scalar))
+    }
+
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> PResult<Node>) -> PResult<Node> {
+        if MAX_DEPTH <= self.depth {
+            return self.error(format!("nested deeper than {MAX_DEPTH} levels"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn array(&mut self) -> PResult<Node> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank()?;
+            if self.eat(']') {
+                return Ok(Node::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank()?;
+            if self.eat(']') {
+                return Ok(Node::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn inline_table(&mut self) -> PResult<Node> {
+        self.expect('{')?;
+        let mut table = Table::new(TableState::Inline);
+        let multiline = self.v1_1();
+        let skip = |parser: &mut Self| {
+            if multiline {
+                parser.skip_blank()
+            } else {
+                parser.skip_ws();
+                Ok(())
+            }
+        };
+        skip(self)?;
+        if self.eat('}') {
+            return Ok(Node::Table(table));
+        }
+        loop {
+            let (keys, node) = self.keyval()?;
+            insert_dotted(&mut table, keys, node)?;
+            skip(self)?;
+            if self.eat('}') {
+                return Ok(Node::Table(table));
+            }
+            self.expect(',')?;
+            skip(self)?;
+            if self.peek() == Some('}') {
+                if multiline {
+                    self.pos += 1;
+                    return Ok(Node::Table(table));
+                }
+                return self.error("trailing commas are not allowed in inline tables");
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> PResult<String> {
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    value.push(self.escape()?);
+                }
+                '\n' | '\r' => return self.error("newline in single-line string"),
+                c if is_disallowed_control(c) => {
+                    return self.error(format!("control character {c:?} in string"));
+                }
+                c => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    fn ml_basic_string(&mut self) -> PResult<String> {
+        self.newline()?;
+        let mut value = String::new();
+        loop {
+            if self.rest().starts_with(r#"""""#) {
+                return self.ml_close('"', value);
+            }
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            match c {
+                '\\' => {
+                    self.pos += 1;
+                    let end = self.rest().trim_start_matches([' ', '\t']);
+                    if end.starts_with('\n') || end.starts_with("\r\n") {
+                        loop {
+                            self.skip_ws();
+                            if !self.newline()? {
+                                break;
+                            }
+                        }
+                    } else {
+                        value.push(self.escape()?);
+                    }
+                }
+                '\n' | '\r' => {
+                    if !self.newline()? {
+                        return self.error("expected newline");
+                    }
+                    value.push('\n');
+                }
+                c if is_disallowed_control(c) => {
+                    return self.error(format!("control character {c:?} in string"));
+                }
+                c => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> PResult<String> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some('\'') => {
+                    let value = self.input[start..self.pos].to_owned();
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('\n' | '\r') => return self.error("newline in single-line string"),
+                Some(c) if is_disallowed_control(c) => {
+                    return self.error(format!("control character {c:?} in string"));
+                }
+                Some(c) => self.pos += c.len_utf8(),
+            }
+        }
+    }
+
+    fn ml_literal_string(&mut self) -> PResult<String> {
+        self.newline()?;
+        let mut value = String::new();
+        loop {
+            if self.rest().starts_with("'''") {
+                return self.ml_close('\'', value);
+            }
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some('\n' | '\r') => {
+                    if !self.newline()? {
+                        return self.error("expected newline");
+                    }
+                    value.push('\n');
+                }
+                Some(c) if is_disallowed_control(c) => {
+                    return self.error(format!("control character {c:?} in string"));
+                }
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    /// Handle up to two quotes right before the closing delimiter
+    fn ml_close(&mut self, quote: char, mut value: String) -> PResult<String> {
+        let count = self.rest().chars().take_while(|c| *c == quote).count();
+        if 5 < count {
+            return self.error("too many quotes at the end of a multi-line string");
+        }
+        for _ in 3..count {
+            value.push(quote);
+        }
+        self.pos += count;
+        Ok(value)
+    }
+
+    fn escape(&mut self) -> PResult<char> {
+        let start = self.pos - 1;
+        let Some(c) = self.peek() else {
+            return self.error("unterminated escape");
+        };
+        self.pos += c.len_utf8();
+        let escaped = match c {
+            'b' => '\u{8}',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\u{c}',
+            'r' => '\r',
+            '"' => '"',
+            '\\' => '\\',
+            'e' if self.v1_1() => '\u{1b}',
+            'x' if self.v1_1() => self.hex_escape(start, 2)?,
+            'u' => self.hex_escape(start, 4)?,
+            'U' => self.hex_escape(start, 8)?,
+            c => return Err(self.error_at(start, format!("invalid escape `\\{c}`"))),
+        };
+        Ok(escaped)
+    }
+
+    fn hex_escape(&mut self, start: usize, len: usize) -> PResult<char> {
+        let digits = self
+            .rest()
+            .get(..len)
+            .filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error_at(start, format!("expected {len} hex digits")))?;
+        let code = u32::from_str_radix(digits, 16).expect("validated hex digits");
+        self.pos += len;
+        char::from_u32(code)
+            .ok_or_else(|| self.error_at(start, format!("{code:#X} is not a unicode scalar value")))
+    }
+
+    fn is_date(&self) -> bool {
+        let b = &self.input.as_bytes()[self.pos..];
+        5 <= b.len() && b[..4].iter().all(u8::is_ascii_digit) && b[4] == b'-'
+    }
+
+    fn is_time(&self) -> bool {
+        let b = &self.input.as_bytes()[self.pos..];
+        3 <= b.len() && b[..2].iter().all(u8::is_ascii_digit) && b[2] == b':'
+    }
+
+    fn digits(&mut self, len: usize, what: &str) -> PResult<u32> {
+        let digits = self
+            .rest()
+            .get(..len)
+            .filter(|d| d.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| self.error_at(self.pos, format!("expected {len} digit {what}")))?
+            .parse()
+            .expect("validated digits");
+        self.pos += len;
+        Ok(digits)
+    }
+
+    fn datetime(&mut self) -> PResult<DecodedScalar> {
+        let start = self.pos;
+        let mut normalized = String::new();
+        let has_date = self.is_date();
+        if has_date {
+            let year = self.digits(4, "year")?;
+            self.expect('-')?;
+            let month = self.digits(2, "month")?;
+            self.expect('-')?;
+            let day = self.digits(2, "day")?;
+            if !(1..=12).contains(&month) || day == 0 || days_in_month(year, month) < day {
+                return Err(self.error_at(start, "invalid date"));
+            }
+            normalized.push_str(&self.input[start..self.pos]);
+
+            let time_follows = match self.peek_byte(0) {
+                Some(b'T' | b't') => true,
+                Some(b' ') => {
+                    let b = &self.input.as_bytes()[self.pos + 1..];
+                    3 <= b.len() && b[..2].iter().all(u8::is_ascii_digit) && b[2] == b':'
+                }
+                _ => false,
+            };
+            if !time_follows {
+                return Ok(DecodedScalar::DateLocal(normalized));
+            }
+            self.pos += 1;
+            normalized.push('T');
+        }
+
+        let time_start = self.pos;
+        let hour = self.digits(2, "hour")?;
+        self.expect(':')?;
+        let minute = self.digits(2, "minute")?;
+        let mut second = 0;
+        if self.peek() == Some(':') {
+            self.pos += 1;
+            second = self.digits(2, "second")?;
+            normalized.push_str(&self.input[time_start..self.pos]);
+            if self.eat('.') {
+                let len = self
+                    .rest()
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(self.rest().len());
+                if len == 0 {
+                    return self.error("expected fractional seconds");
+                }
+                normalized.push('.');
+                normalized.push_str(&self.rest()[..len]);
+                self.pos += len;
+            }
+        } else if self.v1_1() {
+            normalized.push_str(&self.input[time_start..self.pos]);
+            normalized.push_str(":00");
+        } else {
+            return self.error("expected `:` and seconds");
+        }
+        if 23 < hour || 59 < minute || 60 < second {
+            return Err(self.error_at(time_start, "invalid time"));
+        }
+        if !has_date {
+            return Ok(DecodedScalar::TimeLocal(normalized));
+        }
+
+        match self.peek() {
+            Some('Z' | 'z') => {
+                self.pos += 1;
+                normalized.push('Z');
+            }
+            Some(sign @ ('+' | '-')) => {
+                let offset_start = self.pos;
+                self.pos += 1;
+                let hour = self.digits(2, "offset hour")?;
+                self.expect(':')?;
+                let minute = self.digits(2, "offset minute")?;
+                if 23 < hour || 59 < minute {
+                    return Err(self.error_at(offset_start, "invalid offset"));
+                }
+                normalized.push(sign);
+                normalized.push_str(&self.input[offset_start + 1..self.pos]);
+            }
+            _ => return Ok(DecodedScalar::DatetimeLocal(normalized)),
+        }
+        Ok(DecodedScalar::Datetime(normalized))
+    }
+
+    fn number(&mut self) -> PResult<DecodedScalar> {
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.')))
+            .unwrap_or(self.rest().len());
+        let token = &self.rest()[..len];
+        let invalid = || self.error_at(start, format!("invalid number `{token}`"));
+
+        let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+        let scalar = if matches!(unsigned, "inf" | "nan") {
+            let float = token.parse::<f64>().map_err(|_| invalid())?;
+            DecodedScalar::Float(match unsigned {
+                "nan" => "nan".to_owned(),
+                _ => DecodedScalar::from(float).as_str().to_owned(),
+            })
+        } else if let Some((radix, digits)) = radix_prefix(token) {
+            if !valid_digits(digits, |c| c.is_digit(radix)) {
+                return Err(invalid());
+            }
+            let value = i64::from_str_radix(&digits.replace('_', ""), radix)
+                .map_err(|_| self.error_at(start, format!("integer `{token}` is out of range")))?;
+            DecodedScalar::from(value)
+        } else if unsigned.contains(['.', 'e', 'E']) {
+            if !valid_float(unsigned) {
+                return Err(invalid());
+            }
+            let float = token
+                .replace('_', "")
+                .parse::<f64>()
+                .map_err(|_| invalid())?;
+            DecodedScalar::from(float)
+        } else {
+            if !valid_decimal(unsigned) {
+                return Err(invalid());
+            }
+            let value = token
+                .replace('_', "")
+                .parse::<i64>()
+                .map_err(|_| self.error_at(start, format!("integer `{token}` is out of range")))?;
+            DecodedScalar::from(value)
+        };
+        self.pos += len;
+        Ok(scalar)
+    }
+}
+
+/// Insert `node` at a possibly dotted key, creating intermediate tables as needed
+fn insert_dotted(table: &mut Table, keys: Vec<Key>, node: Node) -> PResult<()> {
+    let mut keys = keys;
+    let (key, offset) = keys.pop().expect("keys are never empty");
+    let mut table = table;
+    for (parent, parent_offset) in keys {
+        let node = table.get_or_insert(&parent, TableState::Dotted);
+        if !matches!(node, Node::Table(child) if child.state == TableState::Dotted) {
+            let message = format!(
+                "cannot extend `{parent}` with dotted keys, it is already defined as {}",
+                a(node.description())
+            );
+            return Err(ParseError {
+                offset: parent_offset,
+                message,
+            });
+        }
+        let Node::Table(child) = node else {
+            unreachable!("checked above");
+        };
+        table = child;
+    }
+    if let Some(existing) = table.get_mut(&key) {
+        let message = format!(
+            "duplicate key `{key}`, it is already defined as {}",
+            a(existing.description())
+        );
+        return Err(ParseError { offset, message });
+    }
+    table.insert(key, node);
+    Ok(())
+}
+
+fn a(description: &str) -> String {
+    let article = if description.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{article} {description}")
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_disallowed_control(c: char) -> bool {
+    matches!(c, '\u{0}'..='\u{8}' | '\u{a}'..='\u{1f}' | '\u{7f}')
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn radix_prefix(token: &str) -> Option<(u32, &str)> {
+    if let Some(digits) = token.strip_prefix("0x") {
+        Some((16, digits))
+    } else if let Some(digits) = token.strip_prefix("0o") {
+        Some((8, digits))
+    } else {
+        token.strip_prefix("0b").map(|digits| (2, digits))
+    }
+}
+
+/// Non-empty digits with each `_` between two digits
+fn valid_digits(s: &str, is_digit: impl Fn(char) -> bool) -> bool {
+    !s.is_empty()
+        && s.split('_')
+            .all(|group| !group.is_empty() && group.chars().all(&is_digit))
+}
+
+/// Unsigned decimal integer without leading zeros
+pub(crate) fn valid_decimal(s: &str) -> bool {
+    valid_digits(s, |c| c.is_ascii_digit()) && (s == "0" || !s.starts_with('0'))
+}
+
+/// Unsigned float, excluding `inf` and `nan`
+pub(crate) fn valid_float(s: &str) -> bool {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (mantissa, None),
+    };
+    let exponent_valid = exponent
+        .map(|e| e.strip_prefix(['+', '-']).unwrap_or(e))
+        .is_none_or(|e| valid_digits(e, |c| c.is_ascii_digit()));
+    valid_decimal(int)
+        && frac.is_none_or(|f| valid_digits(f, |c| c.is_ascii_digit()))
+        && exponent_valid
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[track_caller]
+    fn parse(version: SpecVersion, toml: &str) -> DecodedValue {
+        ReferenceDecoder::new(version).parse(toml).unwrap()
+    }
+
+    #[track_caller]
+    fn json(json: &str) -> DecodedValue {
+        DecodedValue::from_slice(json.as_bytes()).unwrap()
+    }
+
+    #[track_caller]
+    fn error(version: SpecVersion, toml: &str) -> String {
+        ReferenceDecoder::new(version)
+            .parse(toml)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn scalars() {
+        let actual = parse(
+            SpecVersion::V1_0_0,
+            r#"
+int = +1_000
+hex = 0xDEAD_beef
+oct = 0o755
+bin = 0b1101
+float = 6.626e-3_4
+exp = 1E2
+inf = -inf
+nan = +nan
+bool = true
+odt = 1979-05-27 07:32:00.999-07:00
+ldt = 1979-05-27t07:32:00
+ld = 1979-05-27
+lt = 07:32:00
+"#,
+        );
+        let expected = json(
+            r#"{
+                "int": {"type": "integer", "value": "1000"},
+                "hex": {"type": "integer", "value": "3735928559"},
+                "oct": {"type": "integer", "value": "493"},
+                "bin": {"type": "integer", "value": "13"},
+                "float": {"type": "float", "value": "6.626e-34"},
+                "exp": {"type": "float", "value": "100.0"},
+                "inf": {"type": "float", "value": "-inf"},
+                "nan": {"type": "float", "value": "nan"},
+                "bool": {"type": "bool", "value": "true"},
+                "odt": {"type": "datetime", "value": "1979-05-27T07:32:00.999-07:00"},
+                "ldt": {"type": "datetime-local", "value": "1979-05-27T07:32:00"},
+                "ld": {"type": "date-local", "value": "1979-05-27"},
+                "lt": {"type": "time-local", "value": "07:32:00"}
+            }"#,
+        );
+        assert_eq!(actual.diff(&expected), vec![]);
+    }
+
+    #[test]
+    fn strings() {
+        let actual = parse(
+            SpecVersion::V1_0_0,
+            "basic = \"tab\\t \\\"quoted\\\" \\u00E9 \\U0001F600\"
+literal = 'C:\\path'
+ml = \"\"\"
+one \\
+    two\r
+three\"\"\"\"\"
+ml-literal = '''
+raw \\n'''''
+",
+        );
+        let expected = json(
+            r#"{
+                "basic": {"type": "string", "value": "tab\t \"quoted\" é 😀"},
+                "literal": {"type": "string", "value": "C:\\path"},
+                "ml": {"type": "string", "value": "one two\nthree\"\""},
+                "ml-literal": {"type": "string", "value": "raw \\n''"}
+            }"#,
+        );
+        assert_eq!(actual.diff(&expected), vec![]);
+    }
+
+    #[test]
+    fn tables() {
+        let actual = parse(
+            SpecVersion::V1_0_0,
+            r#"
+"quoted key".'literal' = 1
+dotted . key = 2
+
+[a.b.c]
+d = [ 1, [ "two" ], { three = 3 }, # comment
+]
+
+[a]
+e.f = 4
+
+[a.e.g]
+
+[[aot]]
+x = 1
+[aot.sub]
+[[aot]]
+inline = { y.z = 2, empty = {} }
+"#,
+        );
+        let expected = json(
+            r#"{
+                "quoted key": {"literal": {"type": "integer", "value": "1"}},
+                "dotted": {"key": {"type": "integer", "value": "2"}},
+                "a": {
+                    "b": {"c": {"d": [
+                        {"type": "integer", "value": "1"},
+                        [{"type": "string", "value": "two"}],
+                        {"three": {"type": "integer", "value": "3"}}
+                    ]}},
+                    "e": {"f": {"type": "integer", "value": "4"}, "g": {}}
+                },
+                "aot": [
+                    {"x": {"type": "integer", "value": "1"}, "sub": {}},
+                    {"inline": {"y": {"z": {"type": "integer", "value": "2"}}, "empty": {}}}
+                ]
+            }"#,
+        );
+        assert_eq!(actual.diff(&expected), vec![]);
+    }
+
+    #[test]
+    fn version_differences() {
+        let toml = "esc = \"\\e\\x41\"
+time = 07:32
+dt = 1979-05-27T07:32Z
+inline = {
+  a = 1, # comment
+}
+";
+        let actual = parse(SpecVersion::V1_1_0, toml);
+        let expected = json(
+            r#"{
+                "esc": {"type": "string", "value": "\u001bA"},
+                "time": {"type": "time-local", "value": "07:32:00"},
+                "dt": {"type": "datetime", "value": "1979-05-27T07:32:00Z"},
+                "inline": {"a": {"type": "integer", "value": "1"}}
+            }"#,
+        );
+        assert_eq!(actual.diff(&expected), vec![]);
+
+        for toml in [
+            r#"a = "\e""#,
+            r#"a = "\x41""#,
+            "a = 07:32",
+            "a = 1979-05-27T07:32Z",
+            "a = {\n}",
+            "a = { b = 1, }",
+        ] {
+            assert!(
+                ReferenceDecoder::new(SpecVersion::V1_0_0)
+                    .parse(toml)
+                    .is_err(),
+                "{toml:?} should be invalid in 1.0"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for toml in [
+            "a = 1\na = 2",
+            "[a]\n[a]",
+            "a.b = 1\n[a]",
+            "[a]\nb.c = 1\n[a.b]",
+            "[a.b]\n[a]\nb.c = 1",
+            "a = {}\n[a.b]",
+            "a = { b = 1 }\na.c = 2",
+            "a = [{}]\n[[a]]",
+            "[[a]]\n[a]",
+            "[a]\n[[a]]",
+            "a = 01",
+            "a = 1__0",
+            "a = _1",
+            "a = 0x",
+            "a = +0x1",
+            "a = 1.",
+            "a = .1",
+            "a = 1e",
+            "a = 9223372036854775808",
+            "a = 1979-02-29",
+            "a = 24:00:00",
+            "a = 1979-05-27T07:32:00+24:00",
+            "a = \"\\q\"",
+            "a = \"\\uD800\"",
+            "a = \"\n\"",
+            "a = '\u{7f}'",
+            "# \u{0}",
+            "a = 1 b = 2",
+            "a = \"\"\"\"\"\"\"\"\"",
+            "a\r\n= 1",
+            "a = 1\r",
+            "= 1",
+            "[a]]",
+            "[[a] ]",
+        ] {
+            assert!(
+                ReferenceDecoder::new(SpecVersion::V1_0_0)
+                    .parse(toml)
+                    .is_err(),
+                "{toml:?} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "preserve_order")]
+    fn key_order() {
+        fn sort_keys(value: &mut DecodedValue) {
+            if let DecodedValue::Table(table) = value {
+                table.sort_keys();
+                for value in table.values_mut() {
+                    sort_keys(value);
+                }
+            }
+        }
+
+        let toml = b"b = 1\n[a]\nz = true\ny = []\n";
+        let reference = ReferenceDecoder::new(SpecVersion::V1_0_0);
+        crate::Decoder::verify_key_order(&reference, toml, SpecVersion::V1_0_0).unwrap();
+
+        let sorted = crate::DecoderFn::new("sorted", |data: &[u8]| {
+            let mut value = crate::Decoder::decode(&reference, data)?;
+            sort_keys(&mut value);
+            Ok(value)
+        });
+        let err = crate::Decoder::verify_key_order(&sorted, toml, SpecVersion::V1_0_0).unwrap_err();
+        assert!(
+            err.message()
+                .ends_with(r#"a: expected keys in order ["z", "y"], found ["y", "z"]"#),
+            "{err}"
+        );
+        assert!(
+            err.message()
+                .contains(r#"expected keys in order ["b", "a"]"#),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth: usize| format!("a = {}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(
+            ReferenceDecoder::default()
+                .parse(&nested(MAX_DEPTH - 1))
+                .is_ok()
+        );
+        assert_eq!(
+            error(SpecVersion::V1_0_0, &nested(100_000)),
+            "TOML parse error at line 1, column 132: nested deeper than 128 levels"
+        );
+        let inline = |depth: usize| format!("a = {}{}", "{ a = ".repeat(depth), "}".repeat(depth));
+        assert!(
+            error(SpecVersion::V1_0_0, &inline(100_000)).ends_with("nested deeper than 128 levels")
+        );
+
+        let dotted = |depth: usize| vec!["a"; depth].join(".");
+        assert!(
+            ReferenceDecoder::default()
+                .parse(&format!("[{}]", dotted(MAX_DEPTH)))
+                .is_ok()
+        );
+        assert_eq!(
+            error(SpecVersion::V1_0_0, &format!("[{}]", dotted(100_000))),
+            "TOML parse error at line 1, column 258: nested deeper than 128 levels"
+        );
+        assert_eq!(
+            error(
+                SpecVersion::V1_0_0,
+                &format!("{} = [[]]", dotted(MAX_DEPTH - 1))
+            ),
+            "TOML parse error at line 1, column 258: nested deeper than 128 levels"
+        );
+    }
+
+    #[test]
+    fn error_location() {
+        assert_eq!(
+            error(SpecVersion::V1_0_0, "[a]\nb = 1\n[a]"),
+            "TOML parse error at line 3, column 2: `a` is already defined as a table"
+        );
+        assert_eq!(
+            error(SpecVersion::V1_0_0, "a.b = 1\na.b.c = 2"),
+            "TOML parse error at line 2, column 3: cannot extend `b` with dotted keys, it is already defined as a value"
+        );
+
+        let decoder = ReferenceDecoder::new(SpecVersion::V1_0_0);
+        let err = decoder
+            .parse(
+                "a = 1
+b = é",
+            )
+            .unwrap_err();
+        assert_eq!((err.line(), err.column()), (Some(2), Some(5)));
+        assert_eq!(err.span(), Some(10..10));
+        let err = crate::Decoder::decode(&decoder, b"a = \"\xff\"").unwrap_err();
+        assert_eq!((err.line(), err.column()), (Some(1), Some(6)));
+        assert_eq!(err.span(), Some(5..6));
+
+        assert!(crate::Decoder::verify_invalid_case_at(&decoder, b"a = 1\nb = ", 2, 5).is_ok());
+        assert_eq!(
+            crate::Decoder::verify_invalid_case_at(&decoder, b"a = ", 1, 1)
+                .unwrap_err()
+                .message()
+                .lines()
+                .next(),
+            Some(
+                "Expected error at line 1, column 1 but got line 1, column 5: TOML parse error at line 1, column 5: expected a value"
+            )
+        );
+        let err = crate::Error::new("oops").with_span(6..7);
+        assert_eq!(err.line_column_in(b"a = 1\nb"), Some((2, 1)));
+        assert_eq!(crate::Error::new("oops").line_column_in(b"a"), None);
+    }
+}
@@ -0,0 +1,689 @@
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+
+/// Struct name that marks a datetime, matching the convention used by `toml`
+///
+/// A datetime is serialized as a struct with this name and a single [`DATETIME_FIELD`] holding
+/// the datetime as a string.  The string is classified as a
+/// [`DecodedScalar::Datetime`], [`DecodedScalar::DatetimeLocal`], [`DecodedScalar::DateLocal`], or
+/// [`DecodedScalar::TimeLocal`] based on which parts are present.
+pub const DATETIME_STRUCT: &str = "$__toml_private_Datetime";
+
+/// Field name within [`DATETIME_STRUCT`]
+pub const DATETIME_FIELD: &str = "$__toml_private_datetime";
+
+/// Marks the error for a `None`, so table entries can tell it apart from other errors and skip it
+#[derive(Copy, Clone, Debug)]
+struct UnsupportedNone;
+
+impl std::fmt::Display for UnsupportedNone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        "TOML has no null value".fmt(f)
+    }
+}
+
+impl std::error::Error for UnsupportedNone {}
+
+/// Convert any [`serde::Serialize`] value into a [`DecodedValue`]
+///
+/// `None` struct fields and map values are skipped, like in TOML.
+/// Datetimes follow the [`DATETIME_STRUCT`] convention.
+///
+/// # Example
+///
+/// Implementing [`Decoder`][crate::Decoder] for a serde-based TOML crate:
+/// ```rust,ignore
+/// impl toml_test::Decoder for MyDecoder {
+///     fn decode(&self, data: &[u8]) -> Result<toml_test::DecodedValue, toml_test::Error> {
+///         let data = std::str::from_utf8(data).map_err(toml_test::Error::new)?;
+///         let value = data.parse::<my_toml::Table>().map_err(toml_test::Error::new)?;
+///         toml_test::to_decoded(&value)
+///     }
+///
+///     fn name(&self) -> &str {
+///         "my_toml"
+///     }
+/// }
+/// ```
+pub fn to_decoded<T>(value: &T) -> Result<DecodedValue, crate::Error>
+where
+    T: serde::Serialize + ?Sized,
+{
+    value.serialize(DecodedSerializer)
+}
+
+/// [`serde::Serializer`] producing a [`DecodedValue`], see [`to_decoded`]
+#[derive(Copy, Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct DecodedSerializer;
+
+impl serde::Serializer for DecodedSerializer {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+    type SerializeSeq = SerializeDecodedArray;
+    type SerializeTuple = SerializeDecodedArray;
+    type SerializeTupleStruct = SerializeDecodedArray;
+    type SerializeTupleVariant = SerializeDecodedArray;
+    type SerializeMap = SerializeDecodedTable;
+    type SerializeStruct = SerializeDecodedTable;
+    type SerializeStructVariant = SerializeDecodedTable;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(DecodedValue::Scalar(DecodedScalar::from(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(DecodedValue::Scalar(DecodedScalar::from(v)))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        let v = i64::try_from(v).map_err(|_| out_of_range(v))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        let v = i64::try_from(v).map_err(|_| out_of_range(v))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        let v = i64::try_from(v).map_err(|_| out_of_range(v))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(DecodedValue::Scalar(DecodedScalar::from(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(DecodedValue::Scalar(DecodedScalar::from(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(DecodedValue::Array(
+            v.iter()
+                .map(|b| DecodedValue::Scalar(DecodedScalar::from(i64::from(*b))))
+                .collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(crate::Error::new("unsupported None value").with_source(UnsupportedNone))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(crate::Error::new("unsupported unit value"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(crate::Error::new(format!(
+            "unsupported unit struct `{name}`"
+        )))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let mut table = DecodedTable::default();
+        table.insert(variant.to_owned(), value.serialize(self)?);
+        Ok(DecodedValue::Table(table))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SerializeDecodedArray {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SerializeDecodedArray {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SerializeDecodedTable {
+            kind: TableKind::Table,
+            table: DecodedTable::default(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        let kind = if name == DATETIME_STRUCT {
+            TableKind::Datetime(None)
+        } else {
+            TableKind::Table
+        };
+        Ok(SerializeDecodedTable {
+            kind,
+            table: DecodedTable::default(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SerializeDecodedTable {
+            kind: TableKind::Variant(variant),
+            table: DecodedTable::default(),
+            key: None,
+        })
+    }
+}
+
+fn out_of_range(v: impl std::fmt::Display) -> crate::Error {
+    crate::Error::new(format!("integer {v} is out of range for TOML"))
+}
+
+fn is_unsupported_none(err: &crate::Error) -> bool {
+    std::error::Error::source(err).is_some_and(|source| source.is::<UnsupportedNone>())
+}
+
+/// Serialize a table entry, skipping `None`
+fn serialize_entry<T>(value: &T) -> Result<Option<DecodedValue>, crate::Error>
+where
+    T: serde::Serialize + ?Sized,
+{
+    match value.serialize(DecodedSerializer) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_unsupported_none(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Serialize sequences into a [`DecodedValue::Array`], see [`DecodedSerializer`]
+pub struct SerializeDecodedArray {
+    variant: Option<&'static str>,
+    items: Vec<DecodedValue>,
+}
+
+impl SerializeDecodedArray {
+    fn push<T>(&mut self, value: &T) -> Result<(), crate::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let value = value.serialize(DecodedSerializer).map_err(|err| {
+            if is_unsupported_none(&err) {
+                crate::Error::new("unsupported None value in array")
+            } else {
+                err
+            }
+        })?;
+        self.items.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> DecodedValue {
+        let array = DecodedValue::Array(self.items);
+        match self.variant {
+            Some(variant) => {
+                let mut table = DecodedTable::default();
+                table.insert(variant.to_owned(), array);
+                DecodedValue::Table(table)
+            }
+            None => array,
+        }
+    }
+}
+
+impl serde::ser::SerializeSeq for SerializeDecodedArray {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl serde::ser::SerializeTuple for SerializeDecodedArray {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl serde::ser::SerializeTupleStruct for SerializeDecodedArray {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl serde::ser::SerializeTupleVariant for SerializeDecodedArray {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Serialize maps and structs into a [`DecodedValue::Table`], see [`DecodedSerializer`]
+pub struct SerializeDecodedTable {
+    kind: TableKind,
+    table: DecodedTable,
+    key: Option<String>,
+}
+
+enum TableKind {
+    Table,
+    Variant(&'static str),
+    Datetime(Option<DecodedScalar>),
+}
+
+impl SerializeDecodedTable {
+    fn insert<T>(&mut self, key: &str, value: &T) -> Result<(), crate::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        if let TableKind::Datetime(datetime) = &mut self.kind {
+            if key != DATETIME_FIELD {
+                return Err(crate::Error::new(format!(
+                    "unexpected field `{key}` in `{DATETIME_STRUCT}`"
+                )));
+            }
+            let value = value.serialize(DecodedSerializer)?;
+            let DecodedValue::Scalar(DecodedScalar::String(value)) = value else {
+                return Err(crate::Error::new(format!(
+                    "`{DATETIME_FIELD}` must be a string"
+                )));
+            };
+            *datetime = Some(classify_datetime(&value)?);
+            return Ok(());
+        }
+        if let Some(value) = serialize_entry(value)? {
+            self.table.insert(key.to_owned(), value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<DecodedValue, crate::Error> {
+        match self.kind {
+            TableKind::Table => Ok(DecodedValue::Table(self.table)),
+            TableKind::Variant(variant) => {
+                let mut outer = DecodedTable::default();
+                outer.insert(variant.to_owned(), DecodedValue::Table(self.table));
+                Ok(DecodedValue::Table(outer))
+            }
+            TableKind::Datetime(Some(datetime)) => Ok(DecodedValue::Scalar(datetime)),
+            TableKind::Datetime(None) => Err(crate::Error::new(format!(
+                "missing `{DATETIME_FIELD}` in `{DATETIME_STRUCT}`"
+            ))),
+        }
+    }
+}
+
+impl serde::ser::SerializeMap for SerializeDecodedTable {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        match key.serialize(DecodedSerializer)? {
+            DecodedValue::Scalar(DecodedScalar::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(crate::Error::new("table keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let key = self
+            .key
+            .take()
+            .expect("serde calls `serialize_key` before `serialize_value`");
+        self.insert(&key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeStruct for SerializeDecodedTable {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeStructVariant for SerializeDecodedTable {
+    type Ok = DecodedValue;
+    type Error = crate::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+/// Pick the [`DecodedScalar`] datetime variant based on which parts of a datetime are present
+pub(crate) fn classify_datetime(s: &str) -> Result<DecodedScalar, crate::Error> {
+    let bytes = s.as_bytes();
+    let has_date = 10 <= bytes.len() && bytes[4] == b'-' && bytes[7] == b'-';
+    let time = if has_date {
+        match bytes.get(10) {
+            Some(b'T' | b't' | b' ') => Some(&s[11..]),
+            Some(_) => return Err(crate::Error::new(format!("invalid datetime {s:?}"))),
+            None => None,
+        }
+    } else {
+        Some(s)
+    };
+    let scalar = match (has_date, time) {
+        (true, None) => DecodedScalar::DateLocal(s.to_owned()),
+        (true, Some(time)) => {
+            if time.contains(['Z', 'z', '+', '-']) {
+                DecodedScalar::Datetime(s.to_owned())
+            } else {
+                DecodedScalar::DatetimeLocal(s.to_owned())
+            }
+        }
+        (false, Some(time)) if time.contains(':') => DecodedScalar::TimeLocal(s.to_owned()),
+        (false, _) => return Err(crate::Error::new(format!("invalid datetime {s:?}"))),
+    };
+    Ok(scalar)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Datetime(&'static str);
+
+    impl Datetime {
+        fn value(&self) -> DecodedValue {
+            to_decoded(self).unwrap()
+        }
+    }
+
+    impl serde::Serialize for Datetime {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            use serde::ser::SerializeStruct as _;
+
+            let mut s = serializer.serialize_struct(DATETIME_STRUCT, 1)?;
+            s.serialize_field(DATETIME_FIELD, self.0)?;
+            s.end()
+        }
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(
+            to_decoded(&42u8).unwrap(),
+            DecodedValue::Scalar(DecodedScalar::from(42))
+        );
+        assert_eq!(
+            to_decoded(&1.5f32).unwrap(),
+            DecodedValue::Scalar(DecodedScalar::from(1.5))
+        );
+        assert_eq!(
+            to_decoded(&'c').unwrap(),
+            DecodedValue::Scalar(DecodedScalar::from("c"))
+        );
+        assert_eq!(
+            to_decoded(&true).unwrap(),
+            DecodedValue::Scalar(DecodedScalar::from(true))
+        );
+        assert!(to_decoded(&u64::MAX).is_err());
+        assert!(to_decoded(&()).is_err());
+        assert!(to_decoded(&None::<i32>).is_err());
+    }
+
+    #[test]
+    fn datetimes() {
+        assert_eq!(
+            Datetime("1979-05-27T07:32:00Z").value(),
+            DecodedValue::Scalar(DecodedScalar::Datetime("1979-05-27T07:32:00Z".to_owned()))
+        );
+        assert_eq!(
+            Datetime("1979-05-27 07:32:00-07:00").value(),
+            DecodedValue::Scalar(DecodedScalar::Datetime(
+                "1979-05-27 07:32:00-07:00".to_owned()
+            ))
+        );
+        assert_eq!(
+            Datetime("1979-05-27T07:32:00.999").value(),
+            DecodedValue::Scalar(DecodedScalar::DatetimeLocal(
+                "1979-05-27T07:32:00.999".to_owned()
+            ))
+        );
+        assert_eq!(
+            Datetime("1979-05-27").value(),
+            DecodedValue::Scalar(DecodedScalar::DateLocal("1979-05-27".to_owned()))
+        );
+        assert_eq!(
+            Datetime("07:32:00").value(),
+            DecodedValue::Scalar(DecodedScalar::TimeLocal("07:32:00".to_owned()))
+        );
+        assert!(to_decoded(&Datetime("yesterday")).is_err());
+    }
+
+    #[test]
+    fn structures() {
+        #[derive(serde::Serialize)]
+        struct Config {
+            name: String,
+            skipped: Option<String>,
+            ports: Vec<u16>,
+            owner: Owner,
+            mode: Mode,
+            shape: Shape,
+        }
+
+        #[derive(serde::Serialize)]
+        struct Owner {
+            dob: Datetime,
+        }
+
+        #[derive(serde::Serialize)]
+        enum Mode {
+            Fast,
+        }
+
+        #[derive(serde::Serialize)]
+        enum Shape {
+            Circle { radius: f64 },
+        }
+
+        let config = Config {
+            name: "test".to_owned(),
+            skipped: None,
+            ports: vec![80, 443],
+            owner: Owner {
+                dob: Datetime("1979-05-27"),
+            },
+            mode: Mode::Fast,
+            shape: Shape::Circle { radius: 1.0 },
+        };
+        let expected = DecodedValue::from_slice(
+            br#"{
+                "name": {"type": "string", "value": "test"},
+                "ports": [{"type": "integer", "value": "80"}, {"type": "integer", "value": "443"}],
+                "owner": {"dob": {"type": "date-local", "value": "1979-05-27"}},
+                "mode": {"type": "string", "value": "Fast"},
+                "shape": {"Circle": {"radius": {"type": "float", "value": "1.0"}}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(to_decoded(&config).unwrap(), expected);
+
+        assert!(to_decoded(&vec![Some(1), None]).is_err());
+    }
+
+    #[test]
+    fn none_marker() {
+        struct Failing(&'static str);
+
+        impl serde::Serialize for Failing {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom(self.0))
+            }
+        }
+
+        let skipped = std::collections::BTreeMap::from([("a", None::<i32>)]);
+        assert_eq!(
+            to_decoded(&skipped).unwrap(),
+            DecodedValue::Table(DecodedTable::default())
+        );
+        let failing = std::collections::BTreeMap::from([("a", Failing("unsupported None value"))]);
+        assert_eq!(
+            to_decoded(&failing).unwrap_err().to_string(),
+            "unsupported None value"
+        );
+    }
+}
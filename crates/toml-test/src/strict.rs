@@ -0,0 +1,624 @@
+//! Strict loader for the toml-test tagged-JSON format, see
+//! [`DecodedValue::from_slice_strict`][crate::DecodedValue::from_slice_strict]
+
+use crate::DecodedPath;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::PathSegment;
+use crate::decoded::ScalarType;
+
+pub(crate) fn from_slice(v: &[u8]) -> Result<DecodedValue, crate::Error> {
+    let input = std::str::from_utf8(v).map_err(|err| {
+        let location = Location::new(v, err.valid_up_to());
+        crate::Error::new(format!("invalid tagged JSON at {location}: {err}"))
+            .with_kind(crate::ErrorKind::Protocol)
+    })?;
+    let node = Parser {
+        input,
+        pos: 0,
+        depth: 0,
+    }
+    .parse()
+    .map_err(|(offset, message)| {
+        let location = Location::new(v, offset);
+        crate::Error::new(format!("invalid tagged JSON at {location}: {message}"))
+            .with_kind(crate::ErrorKind::Protocol)
+    })?;
+
+    let mut validator = Validator {
+        input: v,
+        path: DecodedPath::root(),
+        problems: Vec::new(),
+    };
+    let value = validator.value(node);
+    if validator.problems.is_empty() {
+        Ok(value.expect("no problems means a value was produced"))
+    } else {
+        let mut message = format!("{} problems in tagged JSON:", validator.problems.len());
+        for problem in &validator.problems {
+            message.push_str("\n- ");
+            message.push_str(problem);
+        }
+        Err(crate::Error::new(message).with_kind(crate::ErrorKind::Protocol))
+    }
+}
+
+/// 1-based line and column of a byte offset
+pub(crate) struct Location {
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    pub(crate) fn new(input: &[u8], offset: usize) -> Self {
+        let before = &input[..offset.min(input.len())];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let column = String::from_utf8_lossy(&before[line_start..])
+            .chars()
+            .count()
+            + 1;
+        Self { line, column }
+    }
+
+    pub(crate) fn line(&self) -> usize {
+        self.line
+    }
+
+    pub(crate) fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// JSON value annotated with the byte offset it started at
+struct Node {
+    offset: usize,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Object(Vec<Member>),
+    Array(Vec<Node>),
+    String(String),
+    /// `number`, `bool`, or `null`
+    Other(&'static str),
+}
+
+impl NodeKind {
+    fn name(&self) -> &'static str {
+        match self {
+            NodeKind::Object(_) => "object",
+            NodeKind::Array(_) => "array",
+            NodeKind::String(_) => "string",
+            NodeKind::Other(name) => name,
+        }
+    }
+}
+
+struct Member {
+    key: String,
+    offset: usize,
+    value: Node,
+}
+
+type ParseError = (usize, String);
+
+/// How deeply arrays and objects may nest, like `serde_json`
+///
+/// This also bounds the recursion of [`Validator`].
+const MAX_DEPTH: usize = 128;
+
+/// Minimal JSON parser that, unlike `serde_json`, keeps duplicate keys and offsets around
+struct Parser<'i> {
+    input: &'i str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn parse(mut self) -> Result<Node, ParseError> {
+        let node = self.value()?;
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(node)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        (self.pos, message.into())
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", char::from(expected))))
+        }
+    }
+
+    fn value(&mut self) -> Result<Node, ParseError> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let kind = match self.peek() {
+            Some(b'{') => NodeKind::Object(self.nested(Self::object)?),
+            Some(b'[') => NodeKind::Array(self.nested(Self::array)?),
+            Some(b'"') => NodeKind::String(self.string()?),
+            Some(b't') => self.literal("true", "bool")?,
+            Some(b'f') => self.literal("false", "bool")?,
+            Some(b'n') => self.literal("null", "null")?,
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(_) => return Err(self.error("expected a JSON value")),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        Ok(Node { offset, kind })
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(format!("nested deeper than {MAX_DEPTH} levels")));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn object(&mut self) -> Result<Vec<Member>, ParseError> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(members);
+        }
+        loop {
+            self.skip_whitespace();
+            let offset = self.pos;
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.value()?;
+            members.push(Member { key, offset, value });
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(members);
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<Node>, ParseError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"')?;
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.input[self.pos..].chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    value.push(self.escape()?);
+                }
+                '\u{0}'..='\u{1f}' => return Err(self.error("control character in string")),
+                _ => {
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.hex4()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| self.error("invalid escape"));
+                }
+                if !self.input[self.pos..].starts_with("\\u") {
+                    return Err(self.error("unpaired surrogate"));
+                }
+                self.pos += 2;
+                let low = self.hex4()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(self.error("unpaired surrogate"));
+                }
+                let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                return char::from_u32(c).ok_or_else(|| self.error("invalid escape"));
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected 4 hex digits"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).expect("validated hex digits"))
+    }
+
+    fn literal(&mut self, literal: &str, name: &'static str) -> Result<NodeKind, ParseError> {
+        if self.input[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(NodeKind::Other(name))
+        } else {
+            Err(self.error(format!("expected `{literal}`")))
+        }
+    }
+
+    fn number(&mut self) -> Result<NodeKind, ParseError> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let number = &self.input[start..self.pos];
+        if serde_json::from_str::<serde_json::Number>(number).is_err() {
+            self.pos = start;
+            return Err(self.error(format!("invalid number `{number}`")));
+        }
+        Ok(NodeKind::Other("number"))
+    }
+}
+
+/// Convert [`Node`]s to [`DecodedValue`]s, collecting every problem along the way
+struct Validator<'i> {
+    input: &'i [u8],
+    path: DecodedPath,
+    problems: Vec<String>,
+}
+
+impl Validator<'_> {
+    fn problem(&mut self, offset: usize, message: impl std::fmt::Display) {
+        let location = Location::new(self.input, offset);
+        self.problems
+            .push(format!("{} ({location}): {message}", self.path));
+    }
+
+    fn value(&mut self, node: Node) -> Option<DecodedValue> {
+        match node.kind {
+            NodeKind::Object(members) => {
+                let tag = members
+                    .iter()
+                    .find(|m| m.key == "type" && matches!(m.value.kind, NodeKind::String(_)));
+                if tag.is_some() {
+                    self.scalar(node.offset, members)
+                } else {
+                    self.table(members)
+                }
+            }
+            NodeKind::Array(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for (i, item) in items.into_iter().enumerate() {
+                    self.path.push(PathSegment::Index(i));
+                    values.extend(self.value(item));
+                    self.path.pop();
+                }
+                Some(DecodedValue::Array(values))
+            }
+            kind => {
+                self.problem(
+                    node.offset,
+                    format_args!(
+                        "expected a table, array, or tagged scalar, found {}",
+                        kind.name()
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    fn check_duplicates(&mut self, members: &[Member]) {
+        let mut seen = std::collections::HashSet::new();
+        for member in members {
+            if !seen.insert(member.key.as_str()) {
+                self.problem(
+                    member.offset,
+                    format_args!("duplicate key {:?}", member.key),
+                );
+            }
+        }
+    }
+
+    fn table(&mut self, members: Vec<Member>) -> Option<DecodedValue> {
+        self.check_duplicates(&members);
+        let mut table = DecodedTable::default();
+        for member in members {
+            self.path.push(PathSegment::Key(member.key.clone()));
+            if let Some(value) = self.value(member.value) {
+                table.insert(member.key, value);
+            }
+            self.path.pop();
+        }
+        Some(DecodedValue::Table(table))
+    }
+
+    fn scalar(&mut self, offset: usize, members: Vec<Member>) -> Option<DecodedValue> {
+        self.check_duplicates(&members);
+        let mut ty = None;
+        let mut value = None;
+        let mut valid = true;
+        for member in members {
+            match (member.key.as_str(), member.value.kind) {
+                ("type", NodeKind::String(tag)) => match ScalarType::from_name(&tag) {
+                    Some(found) => ty = Some(found),
+                    None => {
+                        let expected = ScalarType::ALL.map(ScalarType::name).join(", ");
+                        self.problem(
+                            member.value.offset,
+                            format_args!("unknown type tag {tag:?}, expected one of {expected}"),
+                        );
+                        valid = false;
+                    }
+                },
+                ("value", NodeKind::String(v)) => value = Some((member.value.offset, v)),
+                ("value", kind) => {
+                    self.problem(
+                        member.value.offset,
+                        format_args!("expected a string `value`, found {}", kind.name()),
+                    );
+                    valid = false;
+                }
+                (key, _) => {
+                    self.problem(
+                        member.offset,
+                        format_args!("unexpected key {key:?} in tagged scalar"),
+                    );
+                    valid = false;
+                }
+            }
+        }
+        let Some((value_offset, value)) = value else {
+            if valid {
+                self.problem(offset, "tagged scalar is missing `value`");
+            }
+            return None;
+        };
+        let ty = ty?;
+        if let Err(err) =
+            validate_number(ty, &value).and_then(|()| crate::compare::validate_scalar(ty, &value))
+        {
+            self.problem(value_offset, err);
+            return None;
+        }
+        valid.then(|| DecodedValue::Scalar(ty.scalar(value)))
+    }
+}
+
+/// Check integers and floats against the decimal forms of the TOML grammar
+///
+/// [`validate_scalar`][crate::compare::validate_scalar] is more forgiving, e.g. of `.5` or
+/// `Infinity`.
+fn validate_number(ty: ScalarType, value: &str) -> Result<(), String> {
+    let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+    match ty {
+        ScalarType::Integer => {
+            if !crate::parser::valid_decimal(unsigned) {
+                return Err(format!("invalid integer {value:?}"));
+            }
+            crate::compare::parse_integer(value)?
+                .to_i64()
+                .map(|_| ())
+                .ok_or_else(|| format!("integer {value} is out of range"))
+        }
+        ScalarType::Float => {
+            if matches!(unsigned, "inf" | "nan") || crate::parser::valid_float(unsigned) {
+                Ok(())
+            } else {
+                Err(format!("invalid float {value:?}"))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[track_caller]
+    fn problems(input: &str) -> String {
+        from_slice(input.as_bytes()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn matches_lenient() {
+        let input = r#"{
+            "a": {"type": "integer", "value": "1"},
+            "b": [{"type": "string", "value": "é😀"}],
+            "type": {"c": {"type": "bool", "value": "true"}},
+            "d": {}
+        }"#;
+        let strict = from_slice(input.as_bytes()).unwrap();
+        let lenient = DecodedValue::from_slice(input.as_bytes()).unwrap();
+        assert_eq!(strict, lenient);
+    }
+
+    #[test]
+    fn unknown_tag() {
+        let actual = problems(
+            r#"{
+  "a": {"type": "interger", "value": "1"}
+}"#,
+        );
+        assert_eq!(
+            actual,
+            "1 problems in tagged JSON:
+- a (line 2, column 17): unknown type tag \"interger\", expected one of string, integer, float, bool, datetime, datetime-local, date-local, time-local"
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let actual = problems(
+            r#"{
+  "a": {"type": "integer", "value": "1"},
+  "a": {"type": "integer", "value": "2"},
+  "b": {"type": "integer", "type": "float", "value": "2"}
+}"#,
+        );
+        assert_eq!(
+            actual,
+            "2 problems in tagged JSON:
+- <root> (line 3, column 3): duplicate key \"a\"
+- b (line 4, column 28): duplicate key \"type\""
+        );
+    }
+
+    #[test]
+    fn malformed_scalars() {
+        let actual = problems(
+            r#"{
+  "a": {"type": "bool", "value": "yes"},
+  "b": [{"type": "integer", "value": "1x"}],
+  "c": {"type": "date-local", "value": "1979-13-27"},
+  "d": {"type": "string", "value": 5},
+  "e": {"type": "string", "value": "x", "extra": "y"},
+  "f": {"type": "string"},
+  "g": "bare"
+}"#,
+        );
+        assert_eq!(
+            actual,
+            "7 problems in tagged JSON:
+- a (line 2, column 34): invalid bool \"yes\"
+- b[0] (line 3, column 38): invalid integer \"1x\"
+- c (line 4, column 40): invalid date-local \"1979-13-27\": input is out of range
+- d (line 5, column 36): expected a string `value`, found number
+- e (line 6, column 41): unexpected key \"extra\" in tagged scalar
+- f (line 7, column 8): tagged scalar is missing `value`
+- g (line 8, column 8): expected a table, array, or tagged scalar, found string"
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        for (ty, value) in [
+            ("integer", "-1_000"),
+            ("integer", "+0"),
+            ("integer", "-9223372036854775808"),
+            ("float", "+inf"),
+            ("float", "-nan"),
+            ("float", "6.626e-34"),
+            ("float", "1E+2"),
+            ("float", "-0.0"),
+        ] {
+            let input = format!(r#"{{"a": {{"type": "{ty}", "value": "{value}"}}}}"#);
+            assert!(from_slice(input.as_bytes()).is_ok(), "{ty} {value}");
+        }
+
+        let actual = problems(
+            r#"{
+  "a": {"type": "float", "value": "Infinity"},
+  "b": {"type": "float", "value": "NaN"},
+  "c": {"type": "float", "value": ".5"},
+  "d": {"type": "float", "value": "1."},
+  "e": {"type": "integer", "value": "00001"},
+  "f": {"type": "integer", "value": "99999999999999999999999"},
+  "g": {"type": "integer", "value": "0x10"}
+}"#,
+        );
+        assert_eq!(
+            actual,
+            "7 problems in tagged JSON:
+- a (line 2, column 35): invalid float \"Infinity\"
+- b (line 3, column 35): invalid float \"NaN\"
+- c (line 4, column 35): invalid float \".5\"
+- d (line 5, column 35): invalid float \"1.\"
+- e (line 6, column 37): invalid integer \"00001\"
+- f (line 7, column 37): integer 99999999999999999999999 is out of range
+- g (line 8, column 37): invalid integer \"0x10\""
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(from_slice(nested(MAX_DEPTH).as_bytes()).is_ok());
+        assert_eq!(
+            problems(&nested(100_000)),
+            "invalid tagged JSON at line 1, column 129: nested deeper than 128 levels"
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let actual = problems("{\n  \"a\": [1,]\n}");
+        assert_eq!(
+            actual,
+            "invalid tagged JSON at line 2, column 11: expected a JSON value"
+        );
+    }
+}
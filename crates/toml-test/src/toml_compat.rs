@@ -0,0 +1,226 @@
+use crate::DecodedPath;
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::PathSegment;
+use crate::compat::Primitive;
+
+/// The [`toml`] crate as a [`Decoder`][crate::Decoder] and [`Encoder`][crate::Encoder]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Toml;
+
+impl crate::Decoder for Toml {
+    fn decode(&self, data: &[u8]) -> Result<DecodedValue, crate::Error> {
+        let data = std::str::from_utf8(data).map_err(crate::Error::new)?;
+        let table = data.parse::<toml::Table>().map_err(|err| {
+            let span = err.span();
+            let err = crate::Error::new(err);
+            match span {
+                Some(span) => err.with_span(span),
+                None => err,
+            }
+        })?;
+        Ok(DecodedValue::from(&table))
+    }
+
+    fn name(&self) -> &str {
+        "toml"
+    }
+}
+
+impl crate::Encoder for Toml {
+    fn encode(&self, data: DecodedValue) -> Result<String, crate::Error> {
+        let table = to_table(&data)?;
+        toml::to_string(&table).map_err(crate::Error::new)
+    }
+
+    fn name(&self) -> &str {
+        "toml"
+    }
+}
+
+impl From<&toml::Value> for DecodedValue {
+    fn from(value: &toml::Value) -> Self {
+        let scalar = match value {
+            toml::Value::String(value) => DecodedScalar::from(value),
+            toml::Value::Integer(value) => DecodedScalar::from(*value),
+            toml::Value::Float(value) => DecodedScalar::from(*value),
+            toml::Value::Boolean(value) => DecodedScalar::from(*value),
+            toml::Value::Datetime(value) => crate::compat::from_datetime(value),
+            toml::Value::Array(items) => {
+                return DecodedValue::Array(items.iter().map(DecodedValue::from).collect());
+            }
+            toml::Value::Table(table) => return DecodedValue::from(table),
+        };
+        DecodedValue::Scalar(scalar)
+    }
+}
+
+impl From<toml::Value> for DecodedValue {
+    fn from(value: toml::Value) -> Self {
+        DecodedValue::from(&value)
+    }
+}
+
+impl From<&toml::Table> for DecodedValue {
+    fn from(table: &toml::Table) -> Self {
+        DecodedValue::Table(
+            table
+                .iter()
+                .map(|(k, v)| (k.clone(), DecodedValue::from(v)))
+                .collect::<DecodedTable>(),
+        )
+    }
+}
+
+impl From<toml::Table> for DecodedValue {
+    fn from(table: toml::Table) -> Self {
+        DecodedValue::from(&table)
+    }
+}
+
+impl TryFrom<&DecodedValue> for toml::Value {
+    type Error = crate::Error;
+
+    fn try_from(value: &DecodedValue) -> Result<Self, Self::Error> {
+        to_value(&mut DecodedPath::root(), value)
+    }
+}
+
+impl TryFrom<DecodedValue> for toml::Value {
+    type Error = crate::Error;
+
+    fn try_from(value: DecodedValue) -> Result<Self, Self::Error> {
+        to_value(&mut DecodedPath::root(), &value)
+    }
+}
+
+impl TryFrom<&DecodedValue> for toml::Table {
+    type Error = crate::Error;
+
+    fn try_from(value: &DecodedValue) -> Result<Self, Self::Error> {
+        to_table(value)
+    }
+}
+
+impl TryFrom<DecodedValue> for toml::Table {
+    type Error = crate::Error;
+
+    fn try_from(value: DecodedValue) -> Result<Self, Self::Error> {
+        to_table(&value)
+    }
+}
+
+// `toml::Value::try_from` and `toml::Table::try_from` are inherent `Serialize` conversions that
+// shadow the `TryFrom` impls, so the impls delegate to these instead
+fn to_table(value: &DecodedValue) -> Result<toml::Table, crate::Error> {
+    match to_value(&mut DecodedPath::root(), value)? {
+        toml::Value::Table(table) => Ok(table),
+        _ => Err(crate::Error::new(
+            "cannot convert <root>: TOML documents must be a table",
+        )),
+    }
+}
+
+fn to_value(path: &mut DecodedPath, value: &DecodedValue) -> Result<toml::Value, crate::Error> {
+    let value = match value {
+        DecodedValue::Scalar(scalar) => match Primitive::new(path, scalar)? {
+            Primitive::String(value) => toml::Value::String(value),
+            Primitive::Integer(value) => toml::Value::Integer(value),
+            Primitive::Float(value) => toml::Value::Float(value),
+            Primitive::Bool(value) => toml::Value::Boolean(value),
+            Primitive::Datetime(value) => toml::Value::Datetime(value),
+        },
+        DecodedValue::Table(table) => {
+            let mut converted = toml::Table::new();
+            for (key, value) in crate::decoded::ordered_entries(table) {
+                path.push(PathSegment::Key(key.clone()));
+                converted.insert(key.clone(), to_value(path, value)?);
+                path.pop();
+            }
+            toml::Value::Table(converted)
+        }
+        DecodedValue::Array(items) => {
+            let mut converted = Vec::with_capacity(items.len());
+            for (i, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                converted.push(to_value(path, item)?);
+                path.pop();
+            }
+            toml::Value::Array(converted)
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Decoder as _;
+    use crate::Encoder;
+
+    #[test]
+    fn round_trip() {
+        let toml = r#"
+string = "a"
+integer = 0x10
+float = 1e3
+bool = true
+datetime = 1979-05-27 07:32:00.500-07:00
+datetime-local = 1979-05-27T07:32:00
+date-local = 1979-05-27
+time-local = 07:32:00
+array = [1, [], {}]
+
+[[aot]]
+table = {}
+"#;
+        let decoded = Toml.decode(toml.as_bytes()).unwrap();
+        let expected = crate::decoded!({
+            "string": string("a"),
+            "integer": integer(16),
+            "float": float(1000.0),
+            "bool": bool(true),
+            "datetime": datetime("1979-05-27T07:32:00.5-07:00"),
+            "datetime-local": datetime_local("1979-05-27T07:32:00"),
+            "date-local": date_local("1979-05-27"),
+            "time-local": time_local("07:32:00"),
+            "array": [integer(1), [], {}],
+            "aot": [{ "table": {} }],
+        });
+        assert_eq!(decoded.diff(&expected), []);
+
+        let encoded = Toml.encode(decoded.clone()).unwrap();
+        assert_eq!(Toml.decode(encoded.as_bytes()).unwrap().diff(&decoded), []);
+        Encoder::verify_valid_case(
+            &Toml,
+            expected.to_string_pretty().unwrap().as_bytes(),
+            &Toml,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let mut table = DecodedTable::default();
+        table.insert(
+            "a".to_owned(),
+            DecodedValue::Scalar(DecodedScalar::Integer("9223372036854775808".to_owned())),
+        );
+        assert_eq!(
+            <toml::Value as TryFrom<_>>::try_from(DecodedValue::Table(table))
+                .unwrap_err()
+                .to_string(),
+            "cannot convert a: integer 9223372036854775808 is out of range"
+        );
+
+        let value = DecodedValue::Scalar(DecodedScalar::Datetime("1979-05-27T07:32:00".to_owned()));
+        assert!(<toml::Value as TryFrom<_>>::try_from(value).is_err());
+
+        let value = crate::decoded!([]);
+        assert!(<toml::Table as TryFrom<_>>::try_from(value).is_err());
+
+        let err = Toml.verify_invalid_case_at(b"a = 1\nb = ", 2, 5).unwrap();
+        assert!(err.span().is_some());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::DecodedPath;
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::PathSegment;
+use crate::compat::Primitive;
+
+/// The [`toml_edit`] crate as a [`Decoder`][crate::Decoder] and [`Encoder`][crate::Encoder]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TomlEdit;
+
+impl crate::Decoder for TomlEdit {
+    fn decode(&self, data: &[u8]) -> Result<DecodedValue, crate::Error> {
+        let data = std::str::from_utf8(data).map_err(crate::Error::new)?;
+        let document = data.parse::<toml_edit::DocumentMut>().map_err(|err| {
+            let span = err.span();
+            let err = crate::Error::new(err);
+            match span {
+                Some(span) => err.with_span(span),
+                None => err,
+            }
+        })?;
+        Ok(DecodedValue::from(&document))
+    }
+
+    fn name(&self) -> &str {
+        "toml_edit"
+    }
+}
+
+impl crate::Encoder for TomlEdit {
+    fn encode(&self, data: DecodedValue) -> Result<String, crate::Error> {
+        let document = toml_edit::DocumentMut::try_from(&data)?;
+        Ok(document.to_string())
+    }
+
+    fn name(&self) -> &str {
+        "toml_edit"
+    }
+}
+
+impl From<&toml_edit::Value> for DecodedValue {
+    fn from(value: &toml_edit::Value) -> Self {
+        let scalar = match value {
+            toml_edit::Value::String(value) => DecodedScalar::from(value.value()),
+            toml_edit::Value::Integer(value) => DecodedScalar::from(*value.value()),
+            toml_edit::Value::Float(value) => DecodedScalar::from(*value.value()),
+            toml_edit::Value::Boolean(value) => DecodedScalar::from(*value.value()),
+            toml_edit::Value::Datetime(value) => crate::compat::from_datetime(value.value()),
+            toml_edit::Value::Array(items) => {
+                return DecodedValue::Array(items.iter().map(DecodedValue::from).collect());
+            }
+            toml_edit::Value::InlineTable(table) => return DecodedValue::from(table),
+        };
+        DecodedValue::Scalar(scalar)
+    }
+}
+
+impl From<&toml_edit::InlineTable> for DecodedValue {
+    fn from(table: &toml_edit::InlineTable) -> Self {
+        DecodedValue::Table(
+            table
+                .iter()
+                .map(|(k, v)| (k.to_owned(), DecodedValue::from(v)))
+                .collect::<DecodedTable>(),
+        )
+    }
+}
+
+impl From<&toml_edit::Table> for DecodedValue {
+    fn from(table: &toml_edit::Table) -> Self {
+        DecodedValue::Table(
+            table
+                .iter()
+                .filter_map(|(k, v)| Some((k.to_owned(), DecodedValue::try_from(v).ok()?)))
+                .collect::<DecodedTable>(),
+        )
+    }
+}
+
+impl From<&toml_edit::ArrayOfTables> for DecodedValue {
+    fn from(tables: &toml_edit::ArrayOfTables) -> Self {
+        DecodedValue::Array(tables.iter().map(DecodedValue::from).collect())
+    }
+}
+
+impl From<&toml_edit::DocumentMut> for DecodedValue {
+    fn from(document: &toml_edit::DocumentMut) -> Self {
+        DecodedValue::from(document.as_table())
+    }
+}
+
+/// Fails for [`toml_edit::Item::None`]
+impl TryFrom<&toml_edit::Item> for DecodedValue {
+    type Error = crate::Error;
+
+    fn try_from(item: &toml_edit::Item) -> Result<Self, Self::Error> {
+        match item {
+            toml_edit::Item::None => Err(crate::Error::new("`Item::None` is not a TOML value")),
+            toml_edit::Item::Value(value) => Ok(DecodedValue::from(value)),
+            toml_edit::Item::Table(table) => Ok(DecodedValue::from(table)),
+            toml_edit::Item::ArrayOfTables(tables) => Ok(DecodedValue::from(tables)),
+        }
+    }
+}
+
+/// Tables are converted to inline tables
+impl TryFrom<&DecodedValue> for toml_edit::Value {
+    type Error = crate::Error;
+
+    fn try_from(value: &DecodedValue) -> Result<Self, Self::Error> {
+        to_value(&mut DecodedPath::root(), value)
+    }
+}
+
+/// Tables are converted to standard tables, except within arrays
+impl TryFrom<&DecodedValue> for toml_edit::Item {
+    type Error = crate::Error;
+
+    fn try_from(value: &DecodedValue) -> Result<Self, Self::Error> {
+        to_item(&mut DecodedPath::root(), value)
+    }
+}
+
+/// See [`toml_edit::Item`]'s conversion
+impl TryFrom<&DecodedValue> for toml_edit::DocumentMut {
+    type Error = crate::Error;
+
+    fn try_from(value: &DecodedValue) -> Result<Self, Self::Error> {
+        match toml_edit::Item::try_from(value)? {
+            toml_edit::Item::Table(table) => Ok(toml_edit::DocumentMut::from(table)),
+            _ => Err(crate::Error::new(
+                "cannot convert <root>: TOML documents must be a table",
+            )),
+        }
+    }
+}
+
+fn to_item(path: &mut DecodedPath, value: &DecodedValue) -> Result<toml_edit::Item, crate::Error> {
+    let item = match value {
+        DecodedValue::Table(table) => {
+            let mut converted = toml_edit::Table::new();
+            for (key, value) in crate::decoded::ordered_entries(table) {
+                path.push(PathSegment::Key(key.clone()));
+                converted.insert(key, to_item(path, value)?);
+                path.pop();
+            }
+            toml_edit::Item::Table(converted)
+        }
+        DecodedValue::Array(items)
+            if !items.is_empty() && items.iter().all(|i| matches!(i, DecodedValue::Table(_))) =>
+        {
+            let mut converted = toml_edit::ArrayOfTables::new();
+            for (i, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                let toml_edit::Item::Table(table) = to_item(path, item)? else {
+                    unreachable!("tables are always converted to `Item::Table`");
+                };
+                converted.push(table);
+                path.pop();
+            }
+            toml_edit::Item::ArrayOfTables(converted)
+        }
+        _ => toml_edit::Item::Value(to_value(path, value)?),
+    };
+    Ok(item)
+}
+
+fn to_value(
+    path: &mut DecodedPath,
+    value: &DecodedValue,
+) -> Result<toml_edit::Value, crate::Error> {
+    let value = match value {
+        DecodedValue::Scalar(scalar) => match Primitive::new(path, scalar)? {
+            Primitive::String(value) => toml_edit::Value::from(value),
+            Primitive::Integer(value) => toml_edit::Value::from(value),
+            Primitive::Float(value) => toml_edit::Value::from(value),
+            Primitive::Bool(value) => toml_edit::Value::from(value),
+            Primitive::Datetime(value) => toml_edit::Value::from(value),
+        },
+        DecodedValue::Table(table) => {
+            let mut converted = toml_edit::InlineTable::new();
+            for (key, value) in crate::decoded::ordered_entries(table) {
+                path.push(PathSegment::Key(key.clone()));
+                converted.insert(key, to_value(path, value)?);
+                path.pop();
+            }
+            toml_edit::Value::InlineTable(converted)
+        }
+        DecodedValue::Array(items) => {
+            let mut converted = toml_edit::Array::new();
+            for (i, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                converted.push(to_value(path, item)?);
+                path.pop();
+            }
+            toml_edit::Value::Array(converted)
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Decoder as _;
+    use crate::Encoder;
+
+    #[test]
+    fn round_trip() {
+        let toml = r#"
+string = "a"
+integer = 0x10
+float = 1e3
+bool = true
+datetime = 1979-05-27 07:32:00.500-07:00
+datetime-local = 1979-05-27T07:32:00
+date-local = 1979-05-27
+time-local = 07:32:00
+array = [1, [], {}]
+dotted.key = 1
+
+[[aot]]
+table = {}
+
+[empty]
+"#;
+        let decoded = TomlEdit.decode(toml.as_bytes()).unwrap();
+        let expected = crate::decoded!({
+            "string": string("a"),
+            "integer": integer(16),
+            "float": float(1000.0),
+            "bool": bool(true),
+            "datetime": datetime("1979-05-27T07:32:00.5-07:00"),
+            "datetime-local": datetime_local("1979-05-27T07:32:00"),
+            "date-local": date_local("1979-05-27"),
+            "time-local": time_local("07:32:00"),
+            "array": [integer(1), [], {}],
+            "dotted": { "key": integer(1) },
+            "aot": [{ "table": {} }],
+            "empty": {},
+        });
+        assert_eq!(decoded.diff(&expected), []);
+
+        let encoded = TomlEdit.encode(decoded.clone()).unwrap();
+        assert_eq!(
+            TomlEdit.decode(encoded.as_bytes()).unwrap().diff(&decoded),
+            []
+        );
+        Encoder::verify_valid_case(
+            &TomlEdit,
+            expected.to_string_pretty().unwrap().as_bytes(),
+            &TomlEdit,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let value = crate::decoded!({ "a": [{ "b": float(1.0) }] });
+        let DecodedValue::Table(mut table) = value else {
+            unreachable!()
+        };
+        table.insert(
+            "c".to_owned(),
+            DecodedValue::Scalar(DecodedScalar::Bool("yes".to_owned())),
+        );
+        assert_eq!(
+            toml_edit::DocumentMut::try_from(&DecodedValue::Table(table))
+                .unwrap_err()
+                .to_string(),
+            r#"cannot convert c: invalid bool "yes""#
+        );
+
+        let value = crate::decoded!(integer(1));
+        assert!(toml_edit::DocumentMut::try_from(&value).is_err());
+        assert!(DecodedValue::try_from(&toml_edit::Item::None).is_err());
+
+        let err = TomlEdit
+            .verify_invalid_case_at(b"a = 1\nb = ", 2, 5)
+            .unwrap();
+        assert!(err.span().is_some());
+    }
+}
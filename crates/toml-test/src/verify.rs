@@ -1,27 +1,43 @@
-use std::io::Write;
-
 /// Abstract over your TOML serialization
 pub trait Encoder {
     fn encode(&self, data: crate::decoded::DecodedValue) -> Result<String, crate::Error>;
 
     fn verify_valid_case(&self, decoded: &[u8], fixture: &dyn Decoder) -> Result<(), crate::Error> {
+        self.verify_valid_case_with(decoded, fixture, &crate::ComparePolicy::new())
+    }
+
+    fn verify_valid_case_with(
+        &self,
+        decoded: &[u8],
+        fixture: &dyn Decoder,
+        policy: &crate::ComparePolicy,
+    ) -> Result<(), crate::Error> {
         let decoded_expected = crate::decoded::DecodedValue::from_slice(decoded)?;
         let actual = self.encode(decoded_expected.clone())?;
         let decoded_actual = fixture.decode(actual.as_bytes()).map_err(|err| {
+            // The encoder is at fault unless the fixture couldn't run or answer
+            let kind = if err.kind().is_infrastructure() || err.kind().is_abnormal() {
+                err.kind()
+            } else {
+                crate::ErrorKind::Mismatch
+            };
             crate::Error::new(format!(
                 "Could not parse encoded TOML: {err}\n```\n{actual}\n```"
             ))
+            .with_kind(kind)
+            .with_source(err)
         })?;
 
-        if decoded_actual == decoded_expected {
+        let differences = decoded_expected.diff_with(&decoded_actual, policy);
+        if differences.is_empty() {
             Ok(())
         } else {
             Err(crate::Error::new(format!(
-                "Unexpected decoding\n```toml\n{}\n```\nExpected\n{}\nActual\n{}",
+                "Unexpected decoding\n```toml\n{}\n```\n{}",
                 actual,
-                decoded_expected.to_string_pretty().unwrap(),
-                decoded_actual.to_string_pretty().unwrap()
-            )))
+                render_differences(&differences),
+            ))
+            .with_kind(crate::ErrorKind::Mismatch))
         }
     }
 
@@ -33,115 +49,114 @@ pub trait Decoder {
     fn decode(&self, data: &[u8]) -> Result<crate::decoded::DecodedValue, crate::Error>;
 
     fn verify_valid_case(&self, fixture: &[u8], expected: &[u8]) -> Result<(), crate::Error> {
+        self.verify_valid_case_with(fixture, expected, &crate::ComparePolicy::new())
+    }
+
+    fn verify_valid_case_with(
+        &self,
+        fixture: &[u8],
+        expected: &[u8],
+        policy: &crate::ComparePolicy,
+    ) -> Result<(), crate::Error> {
         let actual = self.decode(fixture)?;
         let expected = crate::decoded::DecodedValue::from_slice(expected)?;
-        if actual == expected {
+        let differences = expected.diff_with(&actual, policy);
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(format!(
+                "Unexpected decoding\n```toml\n{}\n```\n{}",
+                String::from_utf8_lossy(fixture),
+                render_differences(&differences),
+            ))
+            .with_kind(crate::ErrorKind::Mismatch))
+        }
+    }
+
+    /// Verify tables are decoded with their keys in document order
+    ///
+    /// The document order comes from [`ReferenceDecoder`][crate::ReferenceDecoder] for `version`
+    /// as toml-test's JSON files don't preserve it.  This assumes
+    /// [`Decoder::verify_valid_case`] passes.
+    #[cfg(feature = "preserve_order")]
+    fn verify_key_order(
+        &self,
+        fixture: &[u8],
+        version: crate::SpecVersion,
+    ) -> Result<(), crate::Error> {
+        let actual = self.decode(fixture)?;
+        let expected = crate::ReferenceDecoder::new(version)
+            .decode(fixture)
+            .map_err(|err| {
+                crate::Error::new("reference decoder could not determine the document order")
+                    .with_kind(crate::ErrorKind::Protocol)
+                    .with_source(err)
+            })?;
+        let differences = expected.diff_key_order(&actual);
+        if differences.is_empty() {
             Ok(())
         } else {
             Err(crate::Error::new(format!(
-                "Unexpected decoding\n```toml\n{}\n```\nExpected\n{}\nActual\n{}",
-                std::str::from_utf8(fixture).unwrap(),
-                expected.to_string_pretty().unwrap(),
-                actual.to_string_pretty().unwrap()
-            )))
+                "Unexpected key order\n```toml\n{}\n```\n{}",
+                String::from_utf8_lossy(fixture),
+                render_differences(&differences),
+            ))
+            .with_kind(crate::ErrorKind::Mismatch))
         }
     }
 
+    /// Verify decoding fails
+    ///
+    /// [Infrastructure failures][crate::ErrorKind::is_infrastructure] and
+    /// [abnormal exits][crate::ErrorKind::is_abnormal] are passed through, rather than counted as
+    /// rejecting the case.
     fn verify_invalid_case(&self, fixture: &[u8]) -> Result<crate::Error, crate::Error> {
         match self.decode(fixture) {
             Ok(value) => Err(crate::Error::new(format!(
                 "Should have failed but got:\n{}\n```toml\n{}\n```",
                 value.to_string_pretty().unwrap(),
-                std::str::from_utf8(fixture).unwrap(),
-            ))),
+                String::from_utf8_lossy(fixture),
+            ))
+            .with_kind(crate::ErrorKind::UnexpectedSuccess)),
+            Err(err) if err.kind().is_infrastructure() || err.kind().is_abnormal() => Err(err),
             Err(err) => Ok(err),
         }
     }
 
-    fn name(&self) -> &str;
-}
-
-/// TOML parser-as-a-binary
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Command {
-    bin: std::path::PathBuf,
-}
-
-impl Command {
-    pub fn new(path: impl AsRef<std::path::Path>) -> Self {
-        Self {
-            bin: path.as_ref().to_owned(),
-        }
-    }
-}
-
-impl Encoder for Command {
-    fn encode(&self, data: crate::decoded::DecodedValue) -> Result<String, crate::Error> {
-        let data = data.to_string_pretty()?;
-
-        let mut cmd = std::process::Command::new(&self.bin);
-        cmd.stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        let child = cmd.spawn().map_err(crate::Error::new)?;
-        child
-            .stdin
-            .as_ref()
-            .unwrap()
-            .write_all(data.as_bytes())
-            .map_err(crate::Error::new)?;
-
-        let output = child.wait_with_output().map_err(crate::Error::new)?;
-        if output.status.success() {
-            let output = String::from_utf8(output.stdout).map_err(crate::Error::new)?;
-            Ok(output)
-        } else {
-            let message = String::from_utf8_lossy(&output.stderr);
-            Err(crate::Error::new(format!(
-                "{} failed with {:?}: {}",
-                self.bin.display(),
-                output.status.code(),
-                message
-            )))
+    /// Verify decoding fails at `line` and `column`, starting at 1
+    ///
+    /// The location comes from [`Error::line_column_in`][crate::Error::line_column_in], so a
+    /// decoder must report either a line and column or a span.
+    fn verify_invalid_case_at(
+        &self,
+        fixture: &[u8],
+        line: usize,
+        column: usize,
+    ) -> Result<crate::Error, crate::Error> {
+        let err = self.verify_invalid_case(fixture)?;
+        match err.line_column_in(fixture) {
+            Some(actual) if actual == (line, column) => Ok(err),
+            Some((actual_line, actual_column)) => Err(crate::Error::new(format!(
+                "Expected error at line {line}, column {column} but got line {actual_line}, column {actual_column}: {err}\n```toml\n{}\n```",
+                String::from_utf8_lossy(fixture),
+            ))
+            .with_kind(crate::ErrorKind::Mismatch)),
+            None => Err(crate::Error::new(format!(
+                "Expected error at line {line}, column {column} but got no location: {err}\n```toml\n{}\n```",
+                String::from_utf8_lossy(fixture),
+            ))
+            .with_kind(crate::ErrorKind::Mismatch)),
         }
     }
 
-    fn name(&self) -> &str {
-        self.bin.to_str().expect("we'll always get valid UTF-8")
-    }
+    fn name(&self) -> &str;
 }
 
-impl Decoder for Command {
-    fn decode(&self, data: &[u8]) -> Result<crate::decoded::DecodedValue, crate::Error> {
-        let mut cmd = std::process::Command::new(&self.bin);
-        cmd.stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped());
-        let child = cmd.spawn().map_err(crate::Error::new)?;
-        child
-            .stdin
-            .as_ref()
-            .unwrap()
-            .write_all(data)
-            .map_err(crate::Error::new)?;
-
-        let output = child.wait_with_output().map_err(crate::Error::new)?;
-        if output.status.success() {
-            let output = crate::decoded::DecodedValue::from_slice(&output.stdout)
-                .map_err(crate::Error::new)?;
-            Ok(output)
-        } else {
-            let message = String::from_utf8_lossy(&output.stderr);
-            Err(crate::Error::new(format!(
-                "{} failed with {:?}: {}",
-                self.bin.display(),
-                output.status.code(),
-                message
-            )))
-        }
-    }
-
-    fn name(&self) -> &str {
-        self.bin.to_str().expect("we'll always get valid UTF-8")
+fn render_differences(differences: &[crate::decoded::Difference]) -> String {
+    let mut rendered = format!("{} differences:", differences.len());
+    for difference in differences {
+        rendered.push_str("\n- ");
+        rendered.push_str(&difference.to_string());
     }
+    rendered
 }
@@ -0,0 +1,51 @@
+/// TOML specification version
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum SpecVersion {
+    /// [TOML 1.0.0](https://toml.io/en/v1.0.0)
+    #[default]
+    V1_0_0,
+    /// [TOML 1.1.0](https://toml.io/en/v1.1.0)
+    V1_1_0,
+}
+
+impl SpecVersion {
+    /// All supported versions, oldest first
+    pub const ALL: &'static [SpecVersion] = &[SpecVersion::V1_0_0, SpecVersion::V1_1_0];
+
+    /// The version as used by `toml-test`, e.g. `1.0.0`
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            SpecVersion::V1_0_0 => "1.0.0",
+            SpecVersion::V1_1_0 => "1.1.0",
+        }
+    }
+}
+
+impl std::fmt::Display for SpecVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SpecVersion {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix('v').unwrap_or(s);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|v| v.as_str() == s || v.as_str().strip_suffix(".0") == Some(s))
+            .ok_or_else(|| {
+                let expected = Self::ALL
+                    .iter()
+                    .map(|v| v.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                crate::Error::new(format!(
+                    "unsupported TOML version `{s}`, expected one of {expected}"
+                ))
+            })
+    }
+}
@@ -0,0 +1,413 @@
+use std::fmt::Write as _;
+
+use crate::DecodedPath;
+use crate::DecodedScalar;
+use crate::DecodedTable;
+use crate::DecodedValue;
+use crate::PathSegment;
+use crate::SpecVersion;
+use crate::decoded::write_basic_string;
+use crate::decoded::write_key;
+
+/// Render a [`DecodedValue`] as TOML
+///
+/// The output is meant to be obvious rather than pretty:
+/// - Tables become `[table]` headers, including empty ones
+/// - Non-empty arrays of tables become `[[array]]` headers
+/// - Everything else is written inline
+/// - Keys are sorted unless the `preserve_order` feature is enabled
+///
+/// Every [`DecodedScalar`] keeps its type, e.g. a [`DecodedScalar::DateLocal`] is never written
+/// as a string.
+///
+/// Values nested more than 512 levels deep, counting keys and array indices, are rejected.  This
+/// is deeper than anything [`ReferenceDecoder`][crate::ReferenceDecoder] accepts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReferenceEncoder {
+    version: SpecVersion,
+}
+
+impl ReferenceEncoder {
+    pub const fn new(version: SpecVersion) -> Self {
+        Self { version }
+    }
+
+    pub const fn version(&self) -> SpecVersion {
+        self.version
+    }
+
+    /// Render `value`, which must be a [`DecodedValue::Table`]
+    pub fn to_toml(&self, value: &DecodedValue) -> Result<String, crate::Error> {
+        let DecodedValue::Table(table) = value else {
+            return Err(crate::Error::new(
+                "cannot encode <root>: TOML documents must be a table",
+            ));
+        };
+        let mut writer = Writer {
+            version: self.version,
+            out: String::new(),
+            path: DecodedPath::root(),
+            header: Vec::new(),
+        };
+        writer.table_body(table)?;
+        Ok(writer.out)
+    }
+}
+
+impl crate::Encoder for ReferenceEncoder {
+    fn encode(&self, data: DecodedValue) -> Result<String, crate::Error> {
+        self.to_toml(&data)
+    }
+
+    fn name(&self) -> &str {
+        "toml-test"
+    }
+}
+
+/// Bounds the recursion of [`Writer`]
+const MAX_DEPTH: usize = 512;
+
+struct Writer<'v> {
+    version: SpecVersion,
+    out: String,
+    /// Location of the value being written, for errors
+    path: DecodedPath,
+    /// Keys of the current table header
+    header: Vec<&'v str>,
+}
+
+impl<'v> Writer<'v> {
+    fn table_body(&mut self, table: &'v DecodedTable) -> Result<(), crate::Error> {
+        self.check_depth()?;
+        let entries = entries(table);
+        for (key, value) in &entries {
+            if is_table(value) || is_array_of_tables(value) {
+                continue;
+            }
+            self.path.push(PathSegment::Key((*key).to_owned()));
+            write_key(&mut self.out, key).expect("writing to a `String` can't fail");
+            self.out.push_str(" = ");
+            self.inline(value)?;
+            self.out.push('\n');
+            self.path.pop();
+        }
+        for (key, value) in &entries {
+            match value {
+                DecodedValue::Table(child) => {
+                    self.path.push(PathSegment::Key((*key).to_owned()));
+                    self.header.push(key);
+                    self.write_header("[", "]");
+                    self.table_body(child)?;
+                    self.header.pop();
+                    self.path.pop();
+                }
+                DecodedValue::Array(items) if is_array_of_tables(value) => {
+                    self.path.push(PathSegment::Key((*key).to_owned()));
+                    self.header.push(key);
+                    for (i, item) in items.iter().enumerate() {
+                        let DecodedValue::Table(child) = item else {
+                            unreachable!("checked by `is_array_of_tables`");
+                        };
+                        self.path.push(PathSegment::Index(i));
+                        self.write_header("[[", "]]");
+                        self.table_body(child)?;
+                        self.path.pop();
+                    }
+                    self.header.pop();
+                    self.path.pop();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self, open: &str, close: &str) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push_str(open);
+        for (i, key) in self.header.iter().enumerate() {
+            if i != 0 {
+                self.out.push('.');
+            }
+            write_key(&mut self.out, key).expect("writing to a `String` can't fail");
+        }
+        self.out.push_str(close);
+        self.out.push('\n');
+    }
+
+    fn check_depth(&self) -> Result<(), crate::Error> {
+        if MAX_DEPTH < self.path.segments().len() {
+            return Err(crate::Error::new(format!(
+                "cannot encode {}: nested deeper than {MAX_DEPTH} levels",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, value: &DecodedValue) -> Result<(), crate::Error> {
+        self.check_depth()?;
+        match value {
+            DecodedValue::Scalar(scalar) => self.scalar(scalar),
+            DecodedValue::Array(items) => {
+                self.out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.path.push(PathSegment::Index(i));
+                    self.inline(item)?;
+                    self.path.pop();
+                }
+                self.out.push(']');
+                Ok(())
+            }
+            DecodedValue::Table(table) => {
+                self.out.push('{');
+                for (i, (key, value)) in entries(table).into_iter().enumerate() {
+                    self.out.push_str(if i == 0 { " " } else { ", " });
+                    self.path.push(PathSegment::Key(key.to_owned()));
+                    write_key(&mut self.out, key).expect("writing to a `String` can't fail");
+                    self.out.push_str(" = ");
+                    self.inline(value)?;
+                    self.path.pop();
+                }
+                if !table.is_empty() {
+                    self.out.push(' ');
+                }
+                self.out.push('}');
+                Ok(())
+            }
+        }
+    }
+
+    fn scalar(&mut self, scalar: &DecodedScalar) -> Result<(), crate::Error> {
+        let invalid = |path: &DecodedPath, err: String| {
+            crate::Error::new(format!("cannot encode {path}: {err}"))
+        };
+        let ty = scalar.scalar_type();
+        let value = scalar.as_str();
+        match scalar {
+            DecodedScalar::String(value) => {
+                write_basic_string(&mut self.out, value).expect("writing to a `String` can't fail");
+            }
+            DecodedScalar::Integer(value) => {
+                let integer = crate::compare::parse_integer(value)
+                    .map_err(|err| invalid(&self.path, err))?
+                    .to_i64()
+                    .ok_or_else(|| {
+                        invalid(&self.path, format!("integer {value} is out of range"))
+                    })?;
+                write!(self.out, "{integer}").expect("writing to a `String` can't fail");
+            }
+            DecodedScalar::Float(value) => {
+                let float =
+                    crate::compare::parse_float(value).map_err(|err| invalid(&self.path, err))?;
+                let sign = if float.is_sign_negative() { "-" } else { "" };
+                if float.is_nan() {
+                    write!(self.out, "{sign}nan")
+                } else if float.is_infinite() {
+                    write!(self.out, "{sign}inf")
+                } else {
+                    write!(self.out, "{float:?}")
+                }
+                .expect("writing to a `String` can't fail");
+            }
+            DecodedScalar::Bool(_) => {
+                crate::compare::validate_scalar(ty, value)
+                    .map_err(|err| invalid(&self.path, err))?;
+                self.out.push_str(value);
+            }
+            DecodedScalar::Datetime(_)
+            | DecodedScalar::DatetimeLocal(_)
+            | DecodedScalar::DateLocal(_)
+            | DecodedScalar::TimeLocal(_) => {
+                let with_seconds = crate::compare::with_seconds(value);
+                let full = with_seconds.as_deref().unwrap_or(value);
+                crate::compare::validate_scalar(ty, full)
+                    .map_err(|err| invalid(&self.path, err))?;
+                if self.version < SpecVersion::V1_1_0 {
+                    self.out.push_str(full);
+                } else {
+                    self.out.push_str(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_table(value: &DecodedValue) -> bool {
+    matches!(value, DecodedValue::Table(_))
+}
+
+fn is_array_of_tables(value: &DecodedValue) -> bool {
+    match value {
+        DecodedValue::Array(items) => !items.is_empty() && items.iter().all(is_table),
+        _ => false,
+    }
+}
+
+#[cfg(not(feature = "preserve_order"))]
+fn entries(table: &DecodedTable) -> Vec<(&str, &DecodedValue)> {
+    let mut entries = table
+        .iter()
+        .map(|(k, v)| (k.as_str(), v))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(k, _)| *k);
+    entries
+}
+
+#[cfg(feature = "preserve_order")]
+fn entries(table: &DecodedTable) -> Vec<(&str, &DecodedValue)> {
+    table.iter().map(|(k, v)| (k.as_str(), v)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[track_caller]
+    fn encode(version: SpecVersion, json: &str) -> String {
+        let value = DecodedValue::from_slice(json.as_bytes()).unwrap();
+        ReferenceEncoder::new(version).to_toml(&value).unwrap()
+    }
+
+    #[test]
+    fn scalars() {
+        let actual = encode(
+            SpecVersion::V1_0_0,
+            r#"{
+                "a": {"type": "string", "value": "quote \" tab \t bell \u0007"},
+                "b": {"type": "integer", "value": "+1_000"},
+                "c": {"type": "float", "value": "1e3"},
+                "d": {"type": "float", "value": "-nan"},
+                "e": {"type": "float", "value": "-inf"},
+                "f": {"type": "bool", "value": "false"},
+                "g": {"type": "datetime", "value": "1979-05-27T07:32:00Z"},
+                "h": {"type": "datetime-local", "value": "1979-05-27 07:32:00.5"},
+                "i": {"type": "date-local", "value": "1979-05-27"},
+                "j": {"type": "time-local", "value": "07:32:00"}
+            }"#,
+        );
+        assert_eq!(
+            actual,
+            r#"a = "quote \" tab \t bell \u0007"
+b = 1000
+c = 1000.0
+d = -nan
+e = -inf
+f = false
+g = 1979-05-27T07:32:00Z
+h = 1979-05-27 07:32:00.5
+i = 1979-05-27
+j = 07:32:00
+"#
+        );
+    }
+
+    #[test]
+    fn structure() {
+        let actual = encode(
+            SpecVersion::V1_0_0,
+            r#"{
+                "": {"type": "integer", "value": "2"},
+                "aot": [
+                    {"a": {"type": "integer", "value": "1"}, "sub": {}},
+                    {"nested": [{"b": {"type": "integer", "value": "2"}}]}
+                ],
+                "array": [[], [{"type": "integer", "value": "1"}], [{}, {"x": []}]],
+                "empty": {},
+                "key with spaces": {"type": "integer", "value": "1"},
+                "no-aot": [],
+                "parent": {"child.dot": {"c": {"type": "integer", "value": "3"}}}
+            }"#,
+        );
+        assert_eq!(
+            actual,
+            r#""" = 2
+array = [[], [1], [{}, { x = [] }]]
+"key with spaces" = 1
+no-aot = []
+
+[[aot]]
+a = 1
+
+[aot.sub]
+
+[[aot]]
+
+[[aot.nested]]
+b = 2
+
+[empty]
+
+[parent]
+
+[parent."child.dot"]
+c = 3
+"#
+        );
+    }
+
+    #[test]
+    fn secondless_times() {
+        let json = r#"{
+            "a": {"type": "datetime", "value": "1979-05-27T07:32Z"},
+            "b": {"type": "datetime-local", "value": "1979-05-27T07:32"},
+            "c": {"type": "time-local", "value": "07:32"}
+        }"#;
+        assert_eq!(
+            encode(SpecVersion::V1_0_0, json),
+            "a = 1979-05-27T07:32:00Z\nb = 1979-05-27T07:32:00\nc = 07:32:00\n"
+        );
+        assert_eq!(
+            encode(SpecVersion::V1_1_0, json),
+            "a = 1979-05-27T07:32Z\nb = 1979-05-27T07:32\nc = 07:32\n"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let encoder = ReferenceEncoder::new(SpecVersion::V1_0_0);
+        let value = DecodedValue::Scalar(DecodedScalar::from(1));
+        assert!(encoder.to_toml(&value).is_err());
+
+        let value = DecodedValue::from_slice(
+            br#"{"a": [{"type": "integer", "value": "9223372036854775808"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            encoder.to_toml(&value).unwrap_err().to_string(),
+            "cannot encode a[0]: integer 9223372036854775808 is out of range"
+        );
+
+        let value = DecodedValue::from_slice(br#"{"a": {"type": "date-local", "value": "today"}}"#)
+            .unwrap();
+        assert!(encoder.to_toml(&value).is_err());
+    }
+
+    #[test]
+    fn nesting() {
+        let encoder = ReferenceEncoder::new(SpecVersion::V1_0_0);
+        let nested = |depth: usize| {
+            let mut value = crate::decoded!([]);
+            for _ in 1..depth {
+                value = DecodedValue::Array(vec![value]);
+            }
+            let mut table = DecodedTable::default();
+            table.insert("a".to_owned(), value);
+            DecodedValue::Table(table)
+        };
+        assert!(encoder.to_toml(&nested(MAX_DEPTH)).is_ok());
+        assert!(
+            encoder
+                .to_toml(&nested(MAX_DEPTH + 1))
+                .unwrap_err()
+                .to_string()
+                .ends_with("nested deeper than 512 levels")
+        );
+    }
+}
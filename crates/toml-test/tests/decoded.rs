@@ -4,3 +4,12 @@ fn can_load() {
         toml_test::DecodedValue::from_slice(valid.expected()).unwrap();
     }
 }
+
+#[test]
+fn can_load_borrowed() {
+    for valid in toml_test_data::valid() {
+        let borrowed = toml_test::DecodedValueRef::from_slice(valid.expected()).unwrap();
+        let owned = toml_test::DecodedValue::from_slice(valid.expected()).unwrap();
+        assert_eq!(borrowed, owned);
+    }
+}